@@ -1,66 +1,10 @@
 extern crate biotrees;
 
-use biotrees::phylo::{Phylo, Node};
 use biotrees::phylo::newick::ToNewick;
 
-use biotrees::shape::Shape;
 use biotrees::shape::generator as shape_gen;
 
 
-fn sym_desc_half(t: &Shape) -> u32 {
-    let (sd, _) = t.binary_fold((1u32, 1u32), |t, (sdl, kl), (sdr, kr)| {
-        let kappa = kl + kr;
-
-        match t {
-            Node(ref ts) =>
-                if ts[0].isomorphic(&ts[1]) {
-                    (sdl * sdr * kl, kappa)
-                } else {
-                    (sdl * sdr, kappa)
-                }
-            _ => panic!()
-        }
-    });
-
-    sd
-}
-
-
-fn sym_desc(t: &Shape) -> u32 {
-    let (sd, _) = t.binary_fold((1u32, 1u32), |t, (sdl, kl), (sdr, kr)| {
-        let kappa = kl + kr;
-
-        match t {
-            Node(ref ts) =>
-                if ts[0].isomorphic(&ts[1]) {
-                    (sdl * sdr * kappa, kappa)
-                } else {
-                    (sdl * sdr, kappa)
-                },
-            _ => panic!()
-        }
-    });
-
-    sd
-}
-
-
-fn depth_avg<T>(t: &Phylo<T>) -> f64 {
-    let depths = t.get_leaves_depths();
-    let avg = depths.iter().sum::<u32>() as f64 / depths.len() as f64;
-    avg
-}
-
-
-fn depth_var<T>(t: &Phylo<T>) -> f64 {
-    let depths = t.get_leaves_depths();
-    let avg = depths.iter().sum::<u32>() as f64 / depths.len() as f64;
-    let var = depths.iter().map(|depth| (*depth as f64 - avg).powi(2)).sum::<f64>() / depths.len() as f64;
-
-    var
-}
-
-
 fn main() {
     let args: Vec<String> = std::env::args().collect();
 
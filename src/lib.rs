@@ -1,5 +1,6 @@
 extern crate cached;
 extern crate lazy_static;
+extern crate rand;
 extern crate rayon;
 
 #[macro_use]
@@ -0,0 +1,451 @@
+use std::iter;
+
+use phylo::{Phylo, Leaf, Node, Folded};
+
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct NodeId(usize);
+
+impl NodeId {
+    pub fn index(self) -> usize {
+        self.0
+    }
+
+    pub(crate) fn from_index(i: usize) -> Self {
+        NodeId(i)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct ArenaNode<T> {
+    label: Option<T>,
+    length: Option<f64>,
+    depth: u32,
+    parent: Option<NodeId>,
+    first_child: Option<NodeId>,
+    last_child: Option<NodeId>,
+    next_sibling: Option<NodeId>,
+    prev_sibling: Option<NodeId>
+}
+
+
+// Nodes are stored in a flat vector and always come after their parent, so the root is the
+// first node. Trees converted from a Phylo number their nodes in pre-order. Leaves carry a
+// label and every node may carry the length of the edge to its parent.
+#[derive(Clone, Debug)]
+pub struct IndexedTree<T> {
+    nodes: Vec<ArenaNode<T>>
+}
+
+impl<T> Default for IndexedTree<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> IndexedTree<T> {
+    pub fn new() -> Self {
+        IndexedTree {
+            nodes: Vec::new()
+        }
+    }
+
+    pub fn add_node(&mut self, parent: Option<NodeId>, label: Option<T>) -> NodeId {
+        assert_eq!(parent.is_none(), self.nodes.is_empty());
+
+        let id = NodeId(self.nodes.len());
+
+        let (depth, prev_sibling) = match parent {
+            None    => (0, None),
+            Some(p) => {
+                let pnode = &mut self.nodes[p.0];
+                let prev = pnode.last_child;
+
+                if pnode.first_child.is_none() {
+                    pnode.first_child = Some(id);
+                }
+                pnode.last_child = Some(id);
+
+                (pnode.depth + 1, prev)
+            }
+        };
+
+        if let Some(prev) = prev_sibling {
+            self.nodes[prev.0].next_sibling = Some(id);
+        }
+
+        self.nodes.push(ArenaNode {
+            label,
+            length: None,
+            depth,
+            parent,
+            first_child: None,
+            last_child: None,
+            next_sibling: None,
+            prev_sibling
+        });
+
+        id
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn root(&self) -> NodeId {
+        assert!(!self.nodes.is_empty());
+        NodeId(0)
+    }
+
+    pub fn node_ids(&self) -> impl Iterator<Item=NodeId> {
+        (0..self.nodes.len()).map(NodeId)
+    }
+
+    pub fn label(&self, id: NodeId) -> Option<&T> {
+        self.nodes[id.0].label.as_ref()
+    }
+
+    pub fn length(&self, id: NodeId) -> Option<f64> {
+        self.nodes[id.0].length
+    }
+
+    pub fn set_length(&mut self, id: NodeId, length: Option<f64>) {
+        self.nodes[id.0].length = length;
+    }
+
+    pub fn has_lengths(&self) -> bool {
+        self.nodes.iter().skip(1).any(|n| n.length.is_some())
+    }
+
+    pub fn depth(&self, id: NodeId) -> u32 {
+        self.nodes[id.0].depth
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id.0].parent
+    }
+
+    pub fn first_child(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id.0].first_child
+    }
+
+    pub fn next_sibling(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id.0].next_sibling
+    }
+
+    pub fn prev_sibling(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id.0].prev_sibling
+    }
+
+    pub fn is_leaf(&self, id: NodeId) -> bool {
+        self.nodes[id.0].first_child.is_none()
+    }
+
+    pub fn is_root(&self, id: NodeId) -> bool {
+        self.nodes[id.0].parent.is_none()
+    }
+
+    pub fn children(&self, id: NodeId) -> impl Iterator<Item=NodeId> + '_ {
+        iter::successors(self.first_child(id), move |&ch| self.next_sibling(ch))
+    }
+
+    pub fn num_children(&self, id: NodeId) -> usize {
+        self.children(id).count()
+    }
+
+    pub fn neighbours(&self, id: NodeId) -> impl Iterator<Item=NodeId> + '_ {
+        self.parent(id).into_iter().chain(self.children(id))
+    }
+
+    // Length of the edge between two adjacent nodes.
+    pub fn edge_length(&self, u: NodeId, v: NodeId) -> Option<f64> {
+        if self.parent(u) == Some(v) {
+            self.length(u)
+        } else {
+            assert_eq!(self.parent(v), Some(u));
+            self.length(v)
+        }
+    }
+
+    // Nodes with their neighbour towards `start`, children before parents, as if the tree were
+    // unrooted and hung from `start`.
+    pub fn post_order_from(&self, start: NodeId) -> Vec<(NodeId, Option<NodeId>)> {
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut stack = vec![(start, None, false)];
+
+        while let Some((id, parent, expanded)) = stack.pop() {
+            if expanded {
+                order.push((id, parent));
+            } else {
+                stack.push((id, parent, true));
+                stack.extend(self.neighbours(id)
+                    .filter(|&nb| Some(nb) != parent)
+                    .map(|nb| (nb, Some(id), false)));
+            }
+        }
+
+        order
+    }
+
+    pub fn ancestors(&self, id: NodeId) -> impl Iterator<Item=NodeId> + '_ {
+        iter::successors(self.parent(id), move |&p| self.parent(p))
+    }
+
+    pub fn siblings(&self, id: NodeId) -> impl Iterator<Item=NodeId> + '_ {
+        self.parent(id).into_iter()
+            .flat_map(move |p| self.children(p))
+            .filter(move |&s| s != id)
+    }
+
+    pub fn leaves(&self) -> impl Iterator<Item=NodeId> + '_ {
+        self.node_ids().filter(move |&id| self.is_leaf(id))
+    }
+
+    pub fn find_leaf(&self, label: &T) -> Option<NodeId>
+        where T: PartialEq {
+
+        self.leaves().find(|&id| self.label(id) == Some(label))
+    }
+
+    pub fn pre_order(&self) -> Vec<NodeId> {
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut stack: Vec<NodeId> = self.nodes.first().map(|_| NodeId(0)).into_iter().collect();
+
+        while let Some(id) = stack.pop() {
+            order.push(id);
+
+            let mut ch = self.nodes[id.0].last_child;
+            while let Some(c) = ch {
+                stack.push(c);
+                ch = self.prev_sibling(c);
+            }
+        }
+
+        order
+    }
+
+    pub fn post_order(&self) -> Vec<NodeId> {
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut stack: Vec<(NodeId, Option<NodeId>)> =
+            self.nodes.first().map(|n| (NodeId(0), n.first_child)).into_iter().collect();
+
+        while let Some(top) = stack.last_mut() {
+            match top.1 {
+                Some(ch) => {
+                    top.1 = self.next_sibling(ch);
+                    stack.push((ch, self.first_child(ch)));
+                },
+
+                None => {
+                    order.push(top.0);
+                    stack.pop();
+                }
+            }
+        }
+
+        order
+    }
+
+    pub fn cata<R, L, N>(&self, mut leaf: L, mut node: N) -> R
+        where L: FnMut(NodeId, Option<&T>) -> R,
+              N: FnMut(NodeId, Folded<R>) -> R {
+
+        let mut values = Vec::new();
+
+        for id in self.post_order() {
+            if self.is_leaf(id) {
+                values.push(leaf(id, self.label(id)));
+            } else {
+                let start = values.len() - self.num_children(id);
+                let r = node(id, values.drain(start..));
+                values.push(r);
+            }
+        }
+
+        values.pop().unwrap()
+    }
+
+    // Attributes of every node indexed by id, each computed from its parent's attribute.
+    pub fn inherit<A, F>(&self, root: A, mut f: F) -> Vec<A>
+        where F: FnMut(&A, NodeId) -> A {
+
+        let mut attrs = Vec::with_capacity(self.nodes.len());
+        attrs.push(root);
+
+        for i in 1..self.nodes.len() {
+            let a = f(&attrs[self.nodes[i].parent.unwrap().0], NodeId(i));
+            attrs.push(a);
+        }
+
+        attrs
+    }
+
+    pub fn kappas(&self) -> Vec<u32> {
+        let mut kappas = vec![0; self.nodes.len()];
+
+        for i in (0..self.nodes.len()).rev() {
+            if self.nodes[i].first_child.is_none() {
+                kappas[i] += 1;
+            }
+
+            if let Some(p) = self.nodes[i].parent {
+                kappas[p.0] += kappas[i];
+            }
+        }
+
+        kappas
+    }
+
+    // Edges without a length count as one.
+    pub fn root_distances(&self) -> Vec<f64> {
+        self.inherit(0.0, |&d, id| d + self.length(id).unwrap_or(1.0))
+    }
+
+    pub fn to_phylo(&self) -> Phylo<T>
+        where T: Clone {
+
+        self.cata(|_, x| Leaf(x.expect("unlabelled leaf").clone()), |_, ts| Phylo::node(ts.collect()))
+    }
+}
+
+impl<'a, T: Clone> From<&'a Phylo<T>> for IndexedTree<T> {
+    fn from(t: &'a Phylo<T>) -> Self {
+        let mut tree = IndexedTree::new();
+        let mut stack = vec![(t, None)];
+
+        while let Some((t, parent)) = stack.pop() {
+            match t {
+                Leaf(ref x)  => { tree.add_node(parent, Some(x.clone())); },
+
+                Node(ref ts) => {
+                    let id = tree.add_node(parent, None);
+                    stack.extend(ts.iter().rev().map(|ch| (ch, Some(id))));
+                }
+            }
+        }
+
+        tree
+    }
+}
+
+impl<T: Clone> Phylo<T> {
+    pub fn to_indexed(&self) -> IndexedTree<T> {
+        IndexedTree::from(self)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use phylo::Phylo;
+    use super::{IndexedTree, NodeId};
+
+    // ((a, b, c), d), numbered in pre-order as root 0, (a, b, c) 1, a 2, b 3, c 4 and d 5.
+    fn example() -> Phylo<char> {
+        Phylo::node(vec![
+            Phylo::node(vec![Phylo::leaf_with('a'), Phylo::leaf_with('b'), Phylo::leaf_with('c')]),
+            Phylo::leaf_with('d')
+        ])
+    }
+
+    fn ids(xs: &[usize]) -> Vec<NodeId> {
+        xs.iter().map(|&i| NodeId(i)).collect()
+    }
+
+    #[test]
+    fn conversion_numbers_nodes_in_pre_order() {
+        let t = example().to_indexed();
+
+        assert_eq!(t.len(), 6);
+        assert_eq!(t.pre_order(), ids(&[0, 1, 2, 3, 4, 5]));
+        assert_eq!(t.post_order(), ids(&[2, 3, 4, 1, 5, 0]));
+        assert_eq!(t.leaves().map(|id| *t.label(id).unwrap()).collect::<String>(), "abcd");
+        assert_eq!(t.label(NodeId(1)), None);
+
+        assert_eq!(t.children(NodeId(0)).collect::<Vec<_>>(), ids(&[1, 5]));
+        assert_eq!(t.children(NodeId(1)).collect::<Vec<_>>(), ids(&[2, 3, 4]));
+        assert_eq!(t.parent(NodeId(4)), Some(NodeId(1)));
+        assert_eq!(t.prev_sibling(NodeId(3)), Some(NodeId(2)));
+        assert_eq!(t.next_sibling(NodeId(4)), None);
+        assert_eq!(t.depth(NodeId(3)), 2);
+        assert_eq!(t.kappas(), vec![4, 3, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn conversion_round_trips() {
+        let t = example();
+        assert_eq!(t.to_indexed().to_phylo(), t);
+
+        let leaf = Phylo::leaf_with('a');
+        let indexed = leaf.to_indexed();
+
+        assert_eq!(indexed.len(), 1);
+        assert!(indexed.is_leaf(indexed.root()) && indexed.is_root(indexed.root()));
+        assert_eq!(indexed.kappas(), vec![1]);
+        assert_eq!(indexed.to_phylo(), leaf);
+    }
+
+    #[test]
+    fn deep_trees_convert_without_recursion() {
+        let n = 100_000;
+        let t = (1..n).fold(Phylo::leaf_with(0u32), |t, x| Phylo::node(vec![t, Phylo::leaf_with(x)]));
+        let indexed = t.to_indexed();
+
+        assert_eq!(indexed.len(), 2*n as usize - 1);
+        assert_eq!(indexed.depth(indexed.find_leaf(&0).unwrap()), n - 1);
+        assert_eq!(indexed.to_phylo(), t);
+    }
+
+    #[test]
+    fn neighbourhoods() {
+        let t = example().to_indexed();
+        let b = t.find_leaf(&'b').unwrap();
+
+        assert_eq!(b, NodeId(3));
+        assert_eq!(t.find_leaf(&'e'), None);
+        assert_eq!(t.ancestors(b).collect::<Vec<_>>(), ids(&[1, 0]));
+        assert_eq!(t.siblings(b).collect::<Vec<_>>(), ids(&[2, 4]));
+        assert_eq!(t.siblings(t.root()).count(), 0);
+        assert_eq!(t.neighbours(NodeId(1)).collect::<Vec<_>>(), ids(&[0, 2, 3, 4]));
+
+        // Hung from d: every node follows the nodes behind it.
+        let order = t.post_order_from(NodeId(5));
+        assert_eq!(order.len(), 6);
+        assert_eq!(order.last(), Some(&(NodeId(5), None)));
+        assert!(order.contains(&(NodeId(0), Some(NodeId(5)))));
+        assert!(order.contains(&(NodeId(1), Some(NodeId(0)))));
+    }
+
+    #[test]
+    fn lengths_and_distances() {
+        let mut t = example().to_indexed();
+        assert!(!t.has_lengths());
+
+        t.set_length(NodeId(1), Some(0.5));
+        t.set_length(NodeId(3), Some(2.0));
+
+        assert!(t.has_lengths());
+        assert_eq!(t.edge_length(NodeId(1), NodeId(3)), Some(2.0));
+        assert_eq!(t.edge_length(NodeId(1), NodeId(0)), Some(0.5));
+        assert_eq!(t.root_distances(), vec![0.0, 0.5, 1.5, 2.5, 1.5, 1.0]);
+    }
+
+    #[test]
+    fn trees_built_by_hand() {
+        let mut t = IndexedTree::new();
+        assert!(t.is_empty());
+
+        let root = t.add_node(None, None);
+        let x = t.add_node(Some(root), Some(1u32));
+        let y = t.add_node(Some(root), Some(2));
+
+        assert_eq!(t.children(root).collect::<Vec<_>>(), vec![x, y]);
+        assert_eq!(t.cata(|_, x| *x.unwrap(), |_, xs| xs.sum()), 3);
+        assert_eq!(t.inherit(0, |&d, _| d + 1), vec![0, 1, 1]);
+        assert_eq!(t.to_phylo(), Phylo::node(vec![Phylo::leaf_with(1), Phylo::leaf_with(2)]));
+    }
+}
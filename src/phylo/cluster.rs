@@ -0,0 +1,471 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::ops::{BitAnd, BitOr, BitXor, Sub};
+
+use phylo::Phylo;
+use phylo::arena::IndexedTree;
+
+
+// Numbers the taxa shared by a collection of trees, so that their leaf sets can be compared
+// as bitsets.
+#[derive(Clone, Debug)]
+pub struct TaxonIndex<T> {
+    labels: Vec<T>,
+    indices: HashMap<T, usize>
+}
+
+impl<T: Clone + Hash + Eq> Default for TaxonIndex<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone + Hash + Eq> TaxonIndex<T> {
+    pub fn new() -> Self {
+        TaxonIndex {
+            labels: Vec::new(),
+            indices: HashMap::new()
+        }
+    }
+
+    pub fn from_labels<I>(labels: I) -> Self
+        where I: IntoIterator<Item=T> {
+
+        let mut taxa = Self::new();
+
+        for x in labels {
+            let n = taxa.len();
+            assert_eq!(taxa.insert(x), n, "repeated leaf label");
+        }
+
+        taxa
+    }
+
+    // Taxa are numbered in the order their leaves appear in the tree.
+    pub fn from_tree(tree: &Phylo<T>) -> Self {
+        Self::from_labels(tree.get_leaves().into_iter().cloned())
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    pub fn labels(&self) -> &[T] {
+        &self.labels
+    }
+
+    pub fn insert(&mut self, x: T) -> usize {
+        let labels = &mut self.labels;
+
+        *self.indices.entry(x).or_insert_with_key(|x| {
+            labels.push(x.clone());
+            labels.len() - 1
+        })
+    }
+
+    pub fn index_of(&self, x: &T) -> Option<usize> {
+        self.indices.get(x).cloned()
+    }
+
+    pub fn label(&self, i: usize) -> &T {
+        &self.labels[i]
+    }
+
+    pub fn empty_set(&self) -> LeafSet {
+        LeafSet::new(self.len())
+    }
+
+    pub fn full_set(&self) -> LeafSet {
+        self.empty_set().complement()
+    }
+
+    pub fn leaf_set<'a, I>(&self, xs: I) -> LeafSet
+        where I: IntoIterator<Item=&'a T>,
+              T: 'a {
+
+        let mut set = self.empty_set();
+
+        for x in xs {
+            set.insert(self.index_of(x).expect("leaf not in taxon index"));
+        }
+
+        set
+    }
+
+    pub fn labels_of(&self, set: &LeafSet) -> Vec<&T> {
+        set.iter().map(|i| self.label(i)).collect()
+    }
+}
+
+
+// A subset of the taxa of a TaxonIndex with `n` taxa.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct LeafSet {
+    words: Vec<u64>,
+    n: usize
+}
+
+impl LeafSet {
+    pub fn new(n: usize) -> Self {
+        LeafSet {
+            words: vec![0; n.div_ceil(64)],
+            n
+        }
+    }
+
+    pub fn singleton(n: usize, i: usize) -> Self {
+        let mut set = Self::new(n);
+        set.insert(i);
+        set
+    }
+
+    pub fn universe_len(&self) -> usize {
+        self.n
+    }
+
+    pub fn insert(&mut self, i: usize) -> bool {
+        assert!(i < self.n, "taxon out of range");
+
+        let had = self.contains(i);
+        self.words[i / 64] |= 1 << (i % 64);
+        !had
+    }
+
+    pub fn remove(&mut self, i: usize) -> bool {
+        let had = self.contains(i);
+        self.words[i / 64] &= !(1 << (i % 64));
+        had
+    }
+
+    pub fn contains(&self, i: usize) -> bool {
+        i < self.n && self.words[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|&w| w == 0)
+    }
+
+    pub fn first(&self) -> Option<usize> {
+        self.iter().next()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(k, &w)| {
+            (0..64).filter(move |b| w & (1 << b) != 0).map(move |b| 64*k + b)
+        })
+    }
+
+    fn zip_with<F>(&self, other: &Self, f: F) -> Self
+        where F: Fn(u64, u64) -> u64 {
+
+        assert_eq!(self.n, other.n, "leaf sets over different taxa");
+
+        LeafSet {
+            words: self.words.iter().zip(&other.words).map(|(&a, &b)| f(a, b)).collect(),
+            n: self.n
+        }
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        self.zip_with(other, |a, b| a | b)
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        self.zip_with(other, |a, b| a & b)
+    }
+
+    pub fn difference(&self, other: &Self) -> Self {
+        self.zip_with(other, |a, b| a & !b)
+    }
+
+    pub fn symmetric_difference(&self, other: &Self) -> Self {
+        self.zip_with(other, |a, b| a ^ b)
+    }
+
+    pub fn complement(&self) -> Self {
+        let mut set = LeafSet {
+            words: self.words.iter().map(|w| !w).collect(),
+            n: self.n
+        };
+
+        if !self.n.is_multiple_of(64) {
+            *set.words.last_mut().unwrap() &= (1 << (self.n % 64)) - 1;
+        }
+
+        set
+    }
+
+    pub fn union_with(&mut self, other: &Self) {
+        assert_eq!(self.n, other.n, "leaf sets over different taxa");
+
+        for (a, b) in self.words.iter_mut().zip(&other.words) {
+            *a |= b;
+        }
+    }
+
+    pub fn is_subset(&self, other: &Self) -> bool {
+        self.words.iter().zip(&other.words).all(|(a, b)| a & !b == 0)
+    }
+
+    pub fn is_superset(&self, other: &Self) -> bool {
+        other.is_subset(self)
+    }
+
+    pub fn is_disjoint(&self, other: &Self) -> bool {
+        self.words.iter().zip(&other.words).all(|(a, b)| a & b == 0)
+    }
+
+    // Two clusters are compatible iff they are nested or disjoint.
+    pub fn is_compatible_cluster(&self, other: &Self) -> bool {
+        self.is_subset(other) || other.is_subset(self) || self.is_disjoint(other)
+    }
+
+    // The side of the split {self, leaves - self} that does not contain the first of `leaves`.
+    pub fn split_normal_form(&self, leaves: &Self) -> Self {
+        let side = self.intersection(leaves);
+
+        match leaves.first() {
+            Some(i) if side.contains(i) => leaves.difference(&side),
+            _                           => side
+        }
+    }
+
+    // Two splits of `leaves` are compatible iff some side of one is contained in a side of
+    // the other.
+    pub fn is_compatible_split(&self, other: &Self, leaves: &Self) -> bool {
+        let a = self.intersection(leaves);
+        let b = other.intersection(leaves);
+        let (ac, bc) = (leaves.difference(&a), leaves.difference(&b));
+
+        a.is_disjoint(&b) || a.is_disjoint(&bc) || ac.is_disjoint(&b) || ac.is_disjoint(&bc)
+    }
+
+    pub fn is_trivial_split(&self, leaves: &Self) -> bool {
+        let k = self.intersection(leaves).len();
+        k <= 1 || leaves.len() - k <= 1
+    }
+}
+
+impl BitOr for &LeafSet {
+    type Output = LeafSet;
+
+    fn bitor(self, other: Self) -> LeafSet {
+        self.union(other)
+    }
+}
+
+impl BitAnd for &LeafSet {
+    type Output = LeafSet;
+
+    fn bitand(self, other: Self) -> LeafSet {
+        self.intersection(other)
+    }
+}
+
+impl BitXor for &LeafSet {
+    type Output = LeafSet;
+
+    fn bitxor(self, other: Self) -> LeafSet {
+        self.symmetric_difference(other)
+    }
+}
+
+impl Sub for &LeafSet {
+    type Output = LeafSet;
+
+    fn sub(self, other: Self) -> LeafSet {
+        self.difference(other)
+    }
+}
+
+
+impl<T: Clone + Hash + Eq> IndexedTree<T> {
+    // The cluster of every node, indexed by node id.
+    pub fn clusters(&self, taxa: &TaxonIndex<T>) -> Vec<LeafSet> {
+        let mut clusters = vec![taxa.empty_set(); self.len()];
+
+        for id in self.post_order() {
+            if self.is_leaf(id) {
+                let x = self.label(id).expect("unlabelled leaf");
+                clusters[id.index()].insert(taxa.index_of(x).expect("leaf not in taxon index"));
+            }
+
+            if let Some(p) = self.parent(id) {
+                let (head, tail) = clusters.split_at_mut(id.index());
+                head[p.index()].union_with(&tail[0]);
+            }
+        }
+
+        clusters
+    }
+
+    // The splits induced by the edges of the unrooted version of the tree, in split normal
+    // form with respect to its leaves and in pre-order of their first edge. Trivial splits
+    // are included.
+    pub fn bipartitions(&self, taxa: &TaxonIndex<T>) -> Vec<LeafSet> {
+        let clusters = self.clusters(taxa);
+        let leaves = &clusters[self.root().index()];
+
+        let mut seen = HashSet::new();
+
+        self.node_ids()
+            .filter(|&id| !self.is_root(id))
+            .map(|id| clusters[id.index()].split_normal_form(leaves))
+            .filter(|s| !s.is_empty() && seen.insert(s.clone()))
+            .collect()
+    }
+}
+
+impl<T: Clone + Hash + Eq> Phylo<T> {
+    pub fn taxon_index(&self) -> TaxonIndex<T> {
+        TaxonIndex::from_tree(self)
+    }
+
+    // The cluster of every node, in pre-order.
+    pub fn clusters(&self, taxa: &TaxonIndex<T>) -> Vec<LeafSet> {
+        self.to_indexed().clusters(taxa)
+    }
+
+    pub fn bipartitions(&self, taxa: &TaxonIndex<T>) -> Vec<LeafSet> {
+        self.to_indexed().bipartitions(taxa)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    use phylo::Phylo;
+    use super::{LeafSet, TaxonIndex};
+
+    fn random_set<R: Rng>(n: usize, rng: &mut R) -> (LeafSet, BTreeSet<usize>) {
+        let xs: BTreeSet<usize> = (0..n).filter(|_| rng.random_bool(0.3)).collect();
+        let mut set = LeafSet::new(n);
+
+        for &i in &xs {
+            set.insert(i);
+        }
+
+        (set, xs)
+    }
+
+    #[test]
+    fn set_operations_match_btree_sets() {
+        let mut rng = StdRng::seed_from_u64(39);
+
+        for &n in &[0, 1, 63, 64, 65, 130] {
+            for _ in 0..20 {
+                let (a, xs) = random_set(n, &mut rng);
+                let (b, ys) = random_set(n, &mut rng);
+                let elems = |s: &LeafSet| s.iter().collect::<BTreeSet<usize>>();
+
+                assert_eq!(elems(&a), xs);
+                assert_eq!(a.len(), xs.len());
+                assert_eq!(a.first(), xs.iter().next().cloned());
+                assert_eq!(elems(&(&a | &b)), xs.union(&ys).cloned().collect());
+                assert_eq!(elems(&(&a & &b)), xs.intersection(&ys).cloned().collect());
+                assert_eq!(elems(&(&a - &b)), xs.difference(&ys).cloned().collect());
+                assert_eq!(elems(&(&a ^ &b)), xs.symmetric_difference(&ys).cloned().collect());
+                assert_eq!(elems(&a.complement()), (0..n).filter(|i| !xs.contains(i)).collect());
+                assert_eq!(a.is_subset(&b), xs.is_subset(&ys));
+                assert_eq!(a.is_disjoint(&b), xs.is_disjoint(&ys));
+                assert_eq!(a.is_compatible_cluster(&b),
+                           xs.is_subset(&ys) || ys.is_subset(&xs) || xs.is_disjoint(&ys));
+            }
+        }
+    }
+
+    #[test]
+    fn insertion_and_removal() {
+        let mut set = LeafSet::new(70);
+
+        assert!(set.is_empty());
+        assert!(set.insert(69) && !set.insert(69));
+        assert!(set.contains(69) && !set.contains(70));
+        assert!(set.remove(69) && !set.remove(69));
+        assert_eq!(set, LeafSet::new(70));
+        assert_eq!(LeafSet::singleton(70, 3).iter().collect::<Vec<_>>(), vec![3]);
+    }
+
+    #[test]
+    #[should_panic(expected = "leaf sets over different taxa")]
+    fn sets_over_different_taxa_panic() {
+        LeafSet::new(3).union(&LeafSet::new(4));
+    }
+
+    #[test]
+    fn splits() {
+        let leaves = LeafSet::new(6).complement();
+        let set = |xs: &[usize]| {
+            let mut s = LeafSet::new(6);
+
+            for &i in xs {
+                s.insert(i);
+            }
+
+            s
+        };
+
+        assert_eq!(set(&[0, 1]).split_normal_form(&leaves), set(&[2, 3, 4, 5]));
+        assert_eq!(set(&[2, 3]).split_normal_form(&leaves), set(&[2, 3]));
+        assert!(set(&[4]).is_trivial_split(&leaves));
+        assert!(set(&[0, 1, 2, 3, 4]).is_trivial_split(&leaves));
+        assert!(!set(&[0, 1]).is_trivial_split(&leaves));
+
+        // 01|2345 and 0123|45 are compatible, 01|2345 and 12|0345 are not.
+        assert!(set(&[0, 1]).is_compatible_split(&set(&[4, 5]), &leaves));
+        assert!(set(&[2, 3, 4, 5]).is_compatible_split(&set(&[0, 1, 2, 3]), &leaves));
+        assert!(!set(&[0, 1]).is_compatible_split(&set(&[1, 2]), &leaves));
+    }
+
+    #[test]
+    fn taxon_indices() {
+        let mut taxa = TaxonIndex::from_labels(vec!['c', 'a']);
+
+        assert_eq!(taxa.insert('b'), 2);
+        assert_eq!(taxa.insert('a'), 1);
+        assert_eq!(taxa.labels(), &['c', 'a', 'b']);
+        assert_eq!(taxa.index_of(&'d'), None);
+        assert_eq!(taxa.labels_of(&taxa.leaf_set(&['b', 'c'])), vec![&'c', &'b']);
+        assert_eq!(taxa.full_set().len(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "repeated leaf label")]
+    fn repeated_labels_panic() {
+        TaxonIndex::from_labels(vec!['a', 'b', 'a']);
+    }
+
+    #[test]
+    fn clusters_and_bipartitions() {
+        // ((a, b, c), (d, e))
+        let t = Phylo::node(vec![
+            Phylo::node(vec![Phylo::leaf_with('a'), Phylo::leaf_with('b'), Phylo::leaf_with('c')]),
+            Phylo::node(vec![Phylo::leaf_with('d'), Phylo::leaf_with('e')])
+        ]);
+        let taxa = t.taxon_index();
+        let labels = |s: &LeafSet| taxa.labels_of(s).into_iter().cloned().collect::<String>();
+
+        let clusters: Vec<String> = t.clusters(&taxa).iter().map(labels).collect();
+        assert_eq!(clusters, vec!["abcde", "abc", "a", "b", "c", "de", "d", "e"]);
+
+        // Both edges below the root induce de|abc.
+        let splits: Vec<String> = t.bipartitions(&taxa).iter().map(labels).collect();
+        assert_eq!(splits, vec!["de", "bcde", "b", "c", "d", "e"]);
+
+        let leaf = Phylo::leaf_with('a');
+        assert!(leaf.bipartitions(&leaf.taxon_index()).is_empty());
+    }
+}
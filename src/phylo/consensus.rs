@@ -0,0 +1,440 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+use phylo::{Phylo, Unfold};
+use phylo::arena::{IndexedTree, NodeId};
+use phylo::cluster::{LeafSet, TaxonIndex};
+
+
+// A tree together with the support of each of its nodes, indexed in pre-order.
+#[derive(Clone, Debug)]
+pub struct SupportedTree<T> {
+    pub tree: Phylo<T>,
+    pub support: Vec<f64>
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Credibility {
+    Product,
+    Sum
+}
+
+// A tree chosen from a sample, with its position in the sample and its clade credibility.
+// Products are given by their logarithms.
+#[derive(Clone, Debug)]
+pub struct CredibleTree<T> {
+    pub index: usize,
+    pub score: f64,
+    pub tree: SupportedTree<T>
+}
+
+
+// Number of trees of a sample that contain each cluster.
+pub struct CladeFrequencies<T> {
+    taxa: TaxonIndex<T>,
+    counts: HashMap<LeafSet, u32>,
+    num_trees: usize
+}
+
+impl<T: Clone + Hash + Eq> CladeFrequencies<T> {
+    pub fn new(trees: &[Phylo<T>]) -> Self {
+        assert!(!trees.is_empty(), "no trees");
+
+        let taxa = trees[0].taxon_index();
+        let mut counts = HashMap::new();
+
+        for t in trees {
+            for c in clusters_of(&taxa, &t.to_indexed()).into_iter().collect::<HashSet<_>>() {
+                *counts.entry(c).or_insert(0) += 1;
+            }
+        }
+
+        CladeFrequencies {
+            taxa,
+            counts,
+            num_trees: trees.len()
+        }
+    }
+
+    pub fn taxa(&self) -> &TaxonIndex<T> {
+        &self.taxa
+    }
+
+    pub fn num_trees(&self) -> usize {
+        self.num_trees
+    }
+
+    pub fn count(&self, cluster: &LeafSet) -> u32 {
+        self.counts.get(cluster).cloned().unwrap_or(0)
+    }
+
+    pub fn frequency(&self, cluster: &LeafSet) -> f64 {
+        self.count(cluster) as f64 / self.num_trees as f64
+    }
+
+    // Clusters by decreasing count; ties are broken by decreasing size and then by taxa.
+    pub fn clusters(&self) -> Vec<(&LeafSet, u32)> {
+        let mut cs: Vec<(&LeafSet, u32)> = self.counts.iter().map(|(c, &k)| (c, k)).collect();
+        cs.sort_by(|a, b| b.1.cmp(&a.1).then(b.0.len().cmp(&a.0.len())).then(a.0.cmp(b.0)));
+        cs
+    }
+
+    // The support of every node of a tree over the same taxa, indexed by node id.
+    pub fn support(&self, tree: &IndexedTree<T>) -> Vec<f64> {
+        clusters_of(&self.taxa, tree).iter().map(|c| self.frequency(c)).collect()
+    }
+
+    fn consensus_of<'a, I>(&self, clusters: I) -> SupportedTree<T>
+        where I: IntoIterator<Item=&'a LeafSet> {
+
+        let supported = clusters.into_iter().map(|c| (c.clone(), self.frequency(c))).collect();
+        build_tree(&self.taxa, supported)
+    }
+
+    pub fn strict_consensus(&self) -> SupportedTree<T> {
+        let n = self.num_trees as u32;
+        self.consensus_of(self.counts.iter().filter(|&(_, &k)| k == n).map(|(c, _)| c))
+    }
+
+    // Clusters found in more than the given fraction of the trees, which must be at least
+    // one half for them to be compatible.
+    pub fn majority_consensus(&self, threshold: f64) -> SupportedTree<T> {
+        assert!(threshold >= 0.5, "threshold must be at least 0.5");
+
+        let n = self.num_trees as f64;
+        self.consensus_of(self.counts.iter().filter(|&(_, &k)| k as f64 > threshold * n).map(|(c, _)| c))
+    }
+
+    // Greedily adds the most frequent clusters that are compatible with those already taken.
+    pub fn extended_majority_consensus(&self) -> SupportedTree<T> {
+        let mut taken: Vec<&LeafSet> = Vec::new();
+
+        for (c, _) in self.clusters() {
+            if taken.iter().all(|d| c.is_compatible_cluster(d)) {
+                taken.push(c);
+            }
+        }
+
+        self.consensus_of(taken)
+    }
+
+    // The first of the given trees with the highest product (or sum) of the frequencies of
+    // its clusters.
+    pub fn maximum_clade_credibility(&self, trees: &[Phylo<T>], credibility: Credibility) -> CredibleTree<T> {
+        assert!(!trees.is_empty(), "no trees");
+
+        let mut best: Option<CredibleTree<T>> = None;
+
+        for (index, t) in trees.iter().enumerate() {
+            let support = self.support(&t.to_indexed());
+
+            let score = match credibility {
+                Credibility::Product => support.iter().map(|s| s.ln()).sum(),
+                Credibility::Sum     => support.iter().sum()
+            };
+
+            if best.as_ref().is_none_or(|b| score > b.score) {
+                best = Some(CredibleTree {
+                    index,
+                    score,
+                    tree: SupportedTree { tree: t.clone(), support }
+                });
+            }
+        }
+
+        best.unwrap()
+    }
+}
+
+
+fn clusters_of<T: Clone + Hash + Eq>(taxa: &TaxonIndex<T>, tree: &IndexedTree<T>) -> Vec<LeafSet> {
+    assert!(tree.leaves().all(|id| tree.label(id).is_some_and(|x| taxa.index_of(x).is_some())),
+            "trees have different leaf sets");
+
+    let clusters = tree.clusters(taxa);
+    assert!(clusters[tree.root().index()] == taxa.full_set(), "trees have different leaf sets");
+
+    clusters
+}
+
+// Hangs every cluster from the smallest one containing it. The clusters must be compatible;
+// the full set and the singletons are added when missing, with support one. A single taxon
+// gives a single leaf.
+fn build_tree<T: Clone + Hash + Eq>(taxa: &TaxonIndex<T>, clusters: Vec<(LeafSet, f64)>) -> SupportedTree<T> {
+    let mut nodes: Vec<(LeafSet, f64, Vec<usize>)> = vec![(taxa.full_set(), 1.0, Vec::new())];
+    let singletons = if taxa.len() > 1 { taxa.len() } else { 0 };
+
+    let mut clusters: Vec<(LeafSet, f64)> = clusters.into_iter()
+        .filter(|(c, _)| c.len() > 1 && c.len() < taxa.len())
+        .chain((0..singletons).map(|i| (LeafSet::singleton(taxa.len(), i), 1.0)))
+        .collect();
+
+    clusters.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then(a.0.cmp(&b.0)));
+    clusters.dedup_by(|a, b| a.0 == b.0);
+
+    for (c, support) in clusters {
+        let mut parent = 0;
+
+        while let Some(&ch) = nodes[parent].2.iter().find(|&&ch| c.is_subset(&nodes[ch].0)) {
+            parent = ch;
+        }
+
+        assert!(nodes[parent].2.iter().all(|&ch| c.is_disjoint(&nodes[ch].0)), "incompatible clusters");
+
+        let id = nodes.len();
+        nodes[parent].2.push(id);
+        nodes.push((c, support, Vec::new()));
+    }
+
+    for i in 0..nodes.len() {
+        let mut chs = nodes[i].2.clone();
+        chs.sort_by_key(|&ch| nodes[ch].0.first());
+        nodes[i].2 = chs;
+    }
+
+    let mut support = Vec::with_capacity(nodes.len());
+
+    let tree = Phylo::unfold(0, |i| {
+        let (ref c, s, ref chs) = nodes[i];
+        support.push(s);
+
+        if chs.is_empty() {
+            Unfold::Leaf(taxa.label(c.first().unwrap()).clone())
+        } else {
+            Unfold::Node(chs.clone())
+        }
+    });
+
+    SupportedTree { tree, support }
+}
+
+
+impl<T: Clone + Hash + Eq> Phylo<T> {
+    pub fn strict_consensus(trees: &[Self]) -> SupportedTree<T> {
+        CladeFrequencies::new(trees).strict_consensus()
+    }
+
+    pub fn majority_consensus(trees: &[Self], threshold: f64) -> SupportedTree<T> {
+        CladeFrequencies::new(trees).majority_consensus(threshold)
+    }
+
+    pub fn extended_majority_consensus(trees: &[Self]) -> SupportedTree<T> {
+        CladeFrequencies::new(trees).extended_majority_consensus()
+    }
+
+    pub fn maximum_clade_credibility(trees: &[Self], credibility: Credibility) -> CredibleTree<T> {
+        CladeFrequencies::new(trees).maximum_clade_credibility(trees, credibility)
+    }
+
+    // Adams (1972): the children of the root are the non-empty intersections of the root
+    // partitions of all trees, and every block is then solved on the trees restricted to it.
+    pub fn adams_consensus(trees: &[Self]) -> SupportedTree<T> {
+        let freqs = CladeFrequencies::new(trees);
+        let taxa = freqs.taxa();
+
+        let indexed: Vec<IndexedTree<T>> = trees.iter().map(|t| t.to_indexed()).collect();
+        let clusters: Vec<Vec<LeafSet>> = indexed.iter().map(|t| clusters_of(taxa, t)).collect();
+
+        // The partition of a block in the restriction of a tree is given by the children of
+        // the smallest node containing the block.
+        let partition = |k: usize, block: &LeafSet| -> Vec<LeafSet> {
+            let (t, cs) = (&indexed[k], &clusters[k]);
+            let mut u = t.root();
+
+            while let Some(ch) = t.children(u).find(|ch: &NodeId| block.is_subset(&cs[ch.index()])) {
+                u = ch;
+            }
+
+            t.children(u)
+                .map(|ch| block.intersection(&cs[ch.index()]))
+                .filter(|b| !b.is_empty())
+                .collect()
+        };
+
+        let mut taken = Vec::new();
+        let mut stack = vec![taxa.full_set()];
+
+        while let Some(block) = stack.pop() {
+            if block.len() < 2 {
+                continue;
+            }
+
+            let mut blocks = vec![block.clone()];
+
+            for k in 0..trees.len() {
+                let part = partition(k, &block);
+
+                blocks = blocks.iter()
+                    .flat_map(|b| part.iter().map(move |p| b.intersection(p)))
+                    .filter(|b| !b.is_empty())
+                    .collect();
+            }
+
+            stack.extend(blocks.iter().cloned());
+            taken.extend(blocks);
+        }
+
+        freqs.consensus_of(&taken)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    use phylo::{LabelledTrees, Phylo};
+    use super::{CladeFrequencies, Credibility};
+
+    fn leaf(x: char) -> Phylo<char> {
+        Phylo::leaf_with(x)
+    }
+
+    fn node(ts: Vec<Phylo<char>>) -> Phylo<char> {
+        Phylo::node(ts)
+    }
+
+    // ((a, b), (c, d)), ((a, b), c, d) and ((a, c), (b, d))
+    fn sample() -> Vec<Phylo<char>> {
+        vec![
+            node(vec![node(vec![leaf('a'), leaf('b')]), node(vec![leaf('c'), leaf('d')])]),
+            node(vec![node(vec![leaf('a'), leaf('b')]), leaf('c'), leaf('d')]),
+            node(vec![node(vec![leaf('a'), leaf('c')]), node(vec![leaf('b'), leaf('d')])])
+        ]
+    }
+
+    fn clusters<T: Clone + Ord>(t: &Phylo<T>) -> BTreeSet<Vec<T>> {
+        t.pre_order()
+            .map(|v| {
+                let mut c: Vec<T> = v.node.get_leaves().into_iter().cloned().collect();
+                c.sort();
+                c
+            })
+            .collect()
+    }
+
+    #[test]
+    fn strict_and_majority_consensus() {
+        let trees = sample();
+
+        let strict = Phylo::strict_consensus(&trees);
+        assert_eq!(strict.tree, node(vec![leaf('a'), leaf('b'), leaf('c'), leaf('d')]));
+        assert_eq!(strict.support, vec![1.0; 5]);
+
+        let majority = Phylo::majority_consensus(&trees, 0.5);
+        assert_eq!(majority.tree, node(vec![node(vec![leaf('a'), leaf('b')]), leaf('c'), leaf('d')]));
+        assert_eq!(majority.support, vec![1.0, 2.0 / 3.0, 1.0, 1.0, 1.0, 1.0]);
+
+        let unanimous = Phylo::majority_consensus(&trees, 0.9);
+        assert_eq!(unanimous.tree, strict.tree);
+    }
+
+    #[test]
+    fn extended_majority_consensus() {
+        // After ab, the clusters ac, bd and cd are tied, and only cd is compatible with ab.
+        let extended = Phylo::extended_majority_consensus(&sample());
+
+        assert_eq!(extended.tree, sample()[0]);
+        assert_eq!(extended.support, vec![1.0, 2.0 / 3.0, 1.0, 1.0, 1.0 / 3.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn adams_consensus_keeps_the_nesting_of_a_moving_leaf() {
+        // (((a, x), b), c) and ((a, b), (x, c)) share no cluster, but both nest a and b
+        // together away from c.
+        let trees = vec![
+            node(vec![node(vec![node(vec![leaf('a'), leaf('x')]), leaf('b')]), leaf('c')]),
+            node(vec![node(vec![leaf('a'), leaf('b')]), node(vec![leaf('x'), leaf('c')])])
+        ];
+
+        let strict = Phylo::strict_consensus(&trees);
+        assert_eq!(strict.tree, node(vec![leaf('a'), leaf('x'), leaf('b'), leaf('c')]));
+
+        let adams = Phylo::adams_consensus(&trees);
+        assert_eq!(adams.tree, node(vec![node(vec![leaf('a'), leaf('b')]), leaf('x'), leaf('c')]));
+        assert_eq!(adams.support, vec![1.0, 0.5, 1.0, 1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn consensus_trees_of_random_trees() {
+        let mut rng = StdRng::seed_from_u64(40);
+        let labels: Vec<u32> = (0..8).collect();
+        let trees = LabelledTrees::all(&labels);
+
+        for _ in 0..50 {
+            let sample: Vec<Phylo<u32>> = (0..3)
+                .map(|_| trees.unrank(rng.random_range(0..trees.num_trees())))
+                .collect();
+
+            // The consensus of a single tree is the tree itself.
+            for f in &[Phylo::strict_consensus, Phylo::extended_majority_consensus, Phylo::adams_consensus] {
+                assert_eq!(clusters(&f(&sample[..1]).tree), clusters(&sample[0]));
+            }
+
+            let shared: BTreeSet<Vec<u32>> = sample.iter()
+                .map(clusters)
+                .fold(clusters(&sample[0]), |a, b| a.intersection(&b).cloned().collect());
+
+            assert_eq!(clusters(&Phylo::strict_consensus(&sample).tree), shared);
+
+            // Adams consensus trees contain every cluster of the strict consensus.
+            assert!(shared.is_subset(&clusters(&Phylo::adams_consensus(&sample).tree)));
+
+            let extended = clusters(&Phylo::extended_majority_consensus(&sample).tree);
+            assert!(clusters(&Phylo::majority_consensus(&sample, 0.5).tree).is_subset(&extended));
+        }
+    }
+
+    #[test]
+    fn single_leaf_consensus() {
+        let trees = vec![leaf('a'), leaf('a')];
+
+        assert_eq!(Phylo::strict_consensus(&trees).tree, leaf('a'));
+        assert_eq!(Phylo::adams_consensus(&trees).tree, leaf('a'));
+    }
+
+    #[test]
+    fn maximum_clade_credibility() {
+        let trees = sample();
+
+        // The clusters ab, cd, ac and bd have frequencies 2/3, 1/3, 1/3 and 1/3.
+        let product = Phylo::maximum_clade_credibility(&trees, Credibility::Product);
+        assert_eq!(product.index, 1);
+        assert_eq!(product.score, (2.0f64 / 3.0).ln());
+        assert_eq!(product.tree.tree, trees[1]);
+        assert_eq!(product.tree.support, vec![1.0, 2.0 / 3.0, 1.0, 1.0, 1.0, 1.0]);
+
+        let sum = Phylo::maximum_clade_credibility(&trees, Credibility::Sum);
+        assert_eq!(sum.index, 0);
+        assert_eq!(sum.score, 6.0);
+
+        // Ties go to the first tree.
+        let twice = vec![trees[2].clone(), trees[2].clone()];
+        assert_eq!(Phylo::maximum_clade_credibility(&twice, Credibility::Product).index, 0);
+    }
+
+    #[test]
+    fn support_of_trees_outside_the_sample() {
+        let freqs = CladeFrequencies::new(&sample());
+        let t = node(vec![node(vec![leaf('a'), leaf('b'), leaf('c')]), leaf('d')]);
+
+        assert_eq!(freqs.num_trees(), 3);
+        assert_eq!(freqs.support(&t.to_indexed()), vec![1.0, 0.0, 1.0, 1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "trees have different leaf sets")]
+    fn different_leaf_sets_panic() {
+        Phylo::strict_consensus(&[node(vec![leaf('a'), leaf('b')]), node(vec![leaf('a'), leaf('c')])]);
+    }
+
+    #[test]
+    #[should_panic(expected = "threshold must be at least 0.5")]
+    fn small_thresholds_panic() {
+        Phylo::majority_consensus(&sample(), 0.4);
+    }
+}
@@ -0,0 +1,213 @@
+use phylo::{Phylo, Leaf};
+
+
+// Edits of labelled trees addressed by paths of child indices. They return new trees that
+// share every subtree off the path to the edit with the original one, or None when the path
+// does not lead to a suitable node.
+impl<T: Clone> Phylo<T> {
+    pub fn leaf_path(&self, x: &T) -> Option<Vec<usize>>
+        where T: PartialEq {
+
+        self.find_map_with_path(|v, path| match v.node {
+            Leaf(y) if y == x => Some(path.to_vec()),
+            _                 => None
+        })
+    }
+
+    // Removes the subtree at `path` together with the ancestors left without leaves, and
+    // suppresses the node left with a single child. Returns the pruned tree and the subtree.
+    pub fn prune(&self, path: &[usize]) -> Option<(Self, Self)> {
+        let pruned = self.get_subtree(path)?.clone();
+        let mut z = self.zipper_at(path)?;
+
+        loop {
+            let i = z.index()?;
+            z.up();
+
+            if z.focus().children().len() > 1 {
+                z.down(i);
+                break;
+            }
+        }
+
+        z.remove();
+
+        if z.focus().children().len() == 1 {
+            let ch = z.focus().children()[0].clone();
+            z.replace(ch);
+        }
+
+        Some((z.into_root(), pruned))
+    }
+
+    pub fn remove_leaf(&self, x: &T) -> Option<Self>
+        where T: PartialEq {
+
+        let path = self.leaf_path(x)?;
+        self.prune(&path).map(|(t, _)| t)
+    }
+
+    // Subdivides the edge above the node at `path` and hangs `t` from the new node. Grafting
+    // on the root adds a new root.
+    pub fn graft_on_edge(&self, path: &[usize], t: Self) -> Option<Self> {
+        let mut z = self.zipper_at(path)?;
+        z.modify(|focus| Phylo::node(vec![focus.clone(), t]));
+
+        Some(z.into_root())
+    }
+
+    // Adds `t` as the last child of the internal node at `path`.
+    pub fn graft_on_node(&self, path: &[usize], t: Self) -> Option<Self> {
+        let mut z = self.zipper_at(path)?;
+        let n = z.focus().children().len();
+
+        if z.insert_child(n, t) {
+            Some(z.into_root())
+        } else {
+            None
+        }
+    }
+
+    pub fn insert_leaf_on_edge(&self, path: &[usize], x: T) -> Option<Self> {
+        self.graft_on_edge(path, Leaf(x))
+    }
+
+    pub fn insert_leaf_on_node(&self, path: &[usize], x: T) -> Option<Self> {
+        self.graft_on_node(path, Leaf(x))
+    }
+
+    // Contracts the edge above the internal node at `path`, so that its children become
+    // children of its parent, in its place.
+    pub fn collapse_edge(&self, path: &[usize]) -> Option<Self> {
+        let mut z = self.zipper_at(path)?;
+
+        if z.focus().is_leaf() {
+            return None;
+        }
+
+        let i = z.index()?;
+        let chs = z.focus().children().to_vec();
+        z.up();
+
+        z.modify(|parent| {
+            let mut ts = parent.children().to_vec();
+            ts.splice(i ..= i, chs);
+            Phylo::node(ts)
+        });
+
+        Some(z.into_root())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    use phylo::{LabelledTrees, Phylo};
+
+    fn leaf(x: char) -> Phylo<char> {
+        Phylo::leaf_with(x)
+    }
+
+    fn node(ts: Vec<Phylo<char>>) -> Phylo<char> {
+        Phylo::node(ts)
+    }
+
+    // ((a, b), (c, d, e))
+    fn example() -> Phylo<char> {
+        node(vec![node(vec![leaf('a'), leaf('b')]), node(vec![leaf('c'), leaf('d'), leaf('e')])])
+    }
+
+    #[test]
+    fn pruning() {
+        let t = example();
+
+        let (pruned, subtree) = t.prune(&[0, 1]).unwrap();
+        assert_eq!(pruned, node(vec![leaf('a'), node(vec![leaf('c'), leaf('d'), leaf('e')])]));
+        assert_eq!(subtree, leaf('b'));
+
+        // A multifurcation keeps its other children.
+        assert_eq!(t.remove_leaf(&'d'),
+                   Some(node(vec![node(vec![leaf('a'), leaf('b')]), node(vec![leaf('c'), leaf('e')])])));
+
+        let (pruned, subtree) = t.prune(&[1]).unwrap();
+        assert_eq!(pruned, node(vec![leaf('a'), leaf('b')]));
+        assert_eq!(subtree, t.children()[1]);
+
+        assert_eq!(t.prune(&[]), None);
+        assert_eq!(t.prune(&[2]), None);
+        assert_eq!(t.remove_leaf(&'x'), None);
+    }
+
+    #[test]
+    fn pruning_collapses_a_unary_root() {
+        assert_eq!(node(vec![leaf('a'), leaf('b')]).remove_leaf(&'a'), Some(leaf('b')));
+        assert_eq!(node(vec![node(vec![leaf('a'), leaf('b')]), leaf('c')]).remove_leaf(&'c'),
+                   Some(node(vec![leaf('a'), leaf('b')])));
+        assert_eq!(leaf('a').remove_leaf(&'a'), None);
+    }
+
+    #[test]
+    fn pruning_removes_ancestors_left_without_leaves() {
+        let t = node(vec![node(vec![node(vec![leaf('a')])]), leaf('b'), leaf('c')]);
+        assert_eq!(t.remove_leaf(&'a'), Some(node(vec![leaf('b'), leaf('c')])));
+
+        // Nothing would be left.
+        assert_eq!(node(vec![node(vec![leaf('a')])]).remove_leaf(&'a'), None);
+    }
+
+    #[test]
+    fn removing_leaves_is_restricting_to_the_others() {
+        let mut rng = StdRng::seed_from_u64(43);
+        let labels: Vec<u32> = (0..8).collect();
+        let trees = LabelledTrees::all(&labels);
+
+        for _ in 0..50 {
+            let t = trees.unrank(rng.random_range(0..trees.num_trees()));
+            let x = rng.random_range(0..8);
+            let others: Vec<u32> = labels.iter().cloned().filter(|&y| y != x).collect();
+
+            assert_eq!(t.remove_leaf(&x), Some(t.restrict(&others)));
+        }
+    }
+
+    #[test]
+    fn pruning_shares_the_subtrees_off_the_path() {
+        let t = example();
+        let (pruned, _) = t.prune(&[0, 0]).unwrap();
+
+        assert!(::std::ptr::eq(pruned.children()[1].children().as_ptr(), t.children()[1].children().as_ptr()));
+    }
+
+    #[test]
+    fn grafting() {
+        let t = example();
+
+        assert_eq!(t.insert_leaf_on_edge(&[0, 1], 'x'),
+                   Some(node(vec![node(vec![leaf('a'), node(vec![leaf('b'), leaf('x')])]), t.children()[1].clone()])));
+        assert_eq!(t.insert_leaf_on_edge(&[], 'x'), Some(node(vec![t.clone(), leaf('x')])));
+        assert_eq!(t.insert_leaf_on_node(&[0], 'x'),
+                   Some(node(vec![node(vec![leaf('a'), leaf('b'), leaf('x')]), t.children()[1].clone()])));
+        assert_eq!(t.insert_leaf_on_node(&[0, 0], 'x'), None);
+        assert_eq!(t.insert_leaf_on_edge(&[3], 'x'), None);
+
+        let (pruned, subtree) = t.prune(&[1]).unwrap();
+        assert_eq!(pruned.graft_on_edge(&[], subtree), Some(t.clone()));
+        assert_eq!(leaf('a').leaf_path(&'a'), Some(vec![]));
+        assert_eq!(t.leaf_path(&'e'), Some(vec![1, 2]));
+    }
+
+    #[test]
+    fn collapsing() {
+        let t = example();
+
+        assert_eq!(t.collapse_edge(&[0]), Some(node(vec![leaf('a'), leaf('b'), t.children()[1].clone()])));
+        assert_eq!(t.collapse_edge(&[1]),
+                   Some(node(vec![t.children()[0].clone(), leaf('c'), leaf('d'), leaf('e')])));
+        assert_eq!(t.collapse_edge(&[]), None);
+        assert_eq!(t.collapse_edge(&[1, 0]), None);
+        assert_eq!(t.collapse_edge(&[2]), None);
+    }
+}
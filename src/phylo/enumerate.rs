@@ -0,0 +1,442 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use phylo::{Phylo, Unfold};
+
+
+// Every rooted tree on the labels x0, ..., xn-1 is grown from the leaf x0 by inserting xi, for
+// i from 1, either on an edge of the tree on the previous labels, including a new edge above
+// its root, or as a new child of one of its internal nodes. Removing the leaves in reverse
+// order recovers the insertions, so that trees are ranked by them. Binary trees only have
+// insertions on edges.
+
+enum Insertion {
+    Edge(usize),
+    Node(usize)
+}
+
+// A tree being grown or taken apart, with leaves labelled by the position of their label.
+struct Growth {
+    parent: Vec<Option<usize>>,
+    children: Vec<Vec<usize>>,
+    label: Vec<Option<usize>>,
+    leaf_of: Vec<usize>,
+    root: usize
+}
+
+impl Growth {
+    fn leaf() -> Self {
+        Growth {
+            parent: vec![None],
+            children: vec![Vec::new()],
+            label: vec![Some(0)],
+            leaf_of: vec![0],
+            root: 0
+        }
+    }
+
+    fn add_node(&mut self, parent: Option<usize>, label: Option<usize>) -> usize {
+        let id = self.parent.len();
+        self.parent.push(parent);
+        self.children.push(Vec::new());
+        self.label.push(label);
+
+        if let Some(p) = parent {
+            self.children[p].push(id);
+        }
+
+        if let Some(x) = label {
+            if self.leaf_of.len() <= x {
+                self.leaf_of.resize(x + 1, 0);
+            }
+            self.leaf_of[x] = id;
+        }
+
+        id
+    }
+
+    // Puts `v` in the place of `u` below its parent.
+    fn replace(&mut self, u: usize, v: usize) {
+        let p = self.parent[u];
+        self.parent[v] = p;
+
+        match p {
+            Some(p) => {
+                let i = self.children[p].iter().position(|&ch| ch == u).unwrap();
+                self.children[p][i] = v;
+            },
+            None => self.root = v
+        }
+    }
+
+    fn insert_on_edge(&mut self, v: usize, x: usize) {
+        let w = self.add_node(None, None);
+        self.replace(v, w);
+
+        self.parent[v] = Some(w);
+        self.children[w].push(v);
+        self.add_node(Some(w), Some(x));
+    }
+
+    fn insert_on_node(&mut self, v: usize, x: usize) {
+        self.add_node(Some(v), Some(x));
+    }
+
+    fn remove_leaf(&mut self, x: usize) -> Insertion {
+        let l = self.leaf_of[x];
+        let p = self.parent[l].unwrap();
+        self.children[p].retain(|&ch| ch != l);
+
+        if self.children[p].len() == 1 {
+            let s = self.children[p][0];
+            self.replace(p, s);
+            Insertion::Edge(s)
+        } else {
+            Insertion::Node(p)
+        }
+    }
+
+    // The smallest label below every node and the number of leaves below it. Nodes are
+    // identified by these keys regardless of the order of children.
+    fn keys(&self) -> Vec<(usize, usize)> {
+        let mut order = vec![self.root];
+        let mut i = 0;
+
+        while i < order.len() {
+            order.extend(self.children[order[i]].iter().cloned());
+            i += 1;
+        }
+
+        let mut keys = vec![(usize::MAX, 0); self.parent.len()];
+
+        for &u in order.iter().rev() {
+            if let Some(x) = self.label[u] {
+                keys[u] = (x, 1);
+            }
+
+            if let Some(p) = self.parent[u] {
+                keys[p] = (keys[p].0.min(keys[u].0), keys[p].1 + keys[u].1);
+            }
+        }
+
+        keys
+    }
+
+    // The nodes of the tree, by smallest label below them and then from the root down, and
+    // the internal ones in the same order.
+    fn nodes(&self) -> (Vec<usize>, Vec<usize>) {
+        let keys = self.keys();
+
+        let mut nodes: Vec<usize> = (0..keys.len()).filter(|&u| keys[u].1 > 0).collect();
+        nodes.sort_by_key(|&u| (keys[u].0, keys.len() - keys[u].1));
+
+        let internal = nodes.iter().cloned().filter(|&u| self.label[u].is_none()).collect();
+        (nodes, internal)
+    }
+
+    fn to_phylo<T: Clone>(&self, labels: &[T]) -> Phylo<T> {
+        let keys = self.keys();
+
+        Phylo::unfold(self.root, |u| match self.label[u] {
+            Some(x) => Unfold::Leaf(labels[x].clone()),
+            None    => {
+                let mut chs = self.children[u].clone();
+                chs.sort_by_key(|&ch| keys[ch].0);
+                Unfold::Node(chs)
+            }
+        })
+    }
+}
+
+
+// Numbers of trees on n leaves grown from a tree with i leaves and k internal nodes, indexed
+// by i and k.
+fn completions(n: usize, binary: bool) -> Vec<Vec<u128>> {
+    let mut counts = vec![vec![0u128; n+1]; n+1];
+
+    if n == 0 {
+        return counts;
+    }
+
+    counts[n] = vec![1; n+1];
+
+    for i in (1..n).rev() {
+        for k in 0..i {
+            let on_edges = ((i + k) as u128).checked_mul(counts[i+1][k+1]);
+            let on_nodes = if binary { Some(0) } else { (k as u128).checked_mul(counts[i+1][k]) };
+
+            counts[i][k] = on_edges.and_then(|e| on_nodes.and_then(|v| e.checked_add(v)))
+                .expect("too many trees to count");
+        }
+    }
+
+    counts
+}
+
+// (2n-3)!!
+pub fn count_labelled_binary_trees(n: usize) -> u128 {
+    completions(n, true).get(1).map_or(0, |c| c[0])
+}
+
+// Schröder's fourth problem.
+pub fn count_labelled_trees(n: usize) -> u128 {
+    completions(n, false).get(1).map_or(0, |c| c[0])
+}
+
+
+// The rooted trees on a set of labels, binary or with any multifurcations, in order of rank.
+// Children are sorted by the position of the first of the labels below them.
+pub struct LabelledTrees<T> {
+    labels: Vec<T>,
+    binary: bool,
+    counts: Vec<Vec<u128>>,
+    next: u128
+}
+
+impl<T: Clone> LabelledTrees<T> {
+    // The trees are counted in u128, which holds the binary trees on up to 29 labels and all
+    // the trees on up to 28. Both panic with more labels.
+    pub fn binary(labels: &[T]) -> Self {
+        LabelledTrees::new(labels, true)
+    }
+
+    pub fn all(labels: &[T]) -> Self {
+        LabelledTrees::new(labels, false)
+    }
+
+    fn new(labels: &[T], binary: bool) -> Self {
+        LabelledTrees {
+            labels: labels.to_vec(),
+            binary,
+            counts: completions(labels.len(), binary),
+            next: 0
+        }
+    }
+
+    pub fn labels(&self) -> &[T] {
+        &self.labels
+    }
+
+    pub fn num_trees(&self) -> u128 {
+        self.counts.get(1).map_or(0, |c| c[0])
+    }
+
+    pub fn unrank(&self, mut rank: u128) -> Phylo<T> {
+        assert!(rank < self.num_trees(), "rank out of range");
+
+        let mut growth = Growth::leaf();
+        let mut k = 0;
+
+        for i in 1..self.labels.len() {
+            let (nodes, internal) = growth.nodes();
+            let per_edge = self.counts[i+1][k+1];
+
+            if rank < nodes.len() as u128 * per_edge {
+                growth.insert_on_edge(nodes[(rank / per_edge) as usize], i);
+                rank %= per_edge;
+                k += 1;
+            } else {
+                rank -= nodes.len() as u128 * per_edge;
+
+                let per_node = self.counts[i+1][k];
+                growth.insert_on_node(internal[(rank / per_node) as usize], i);
+                rank %= per_node;
+            }
+        }
+
+        growth.to_phylo(&self.labels)
+    }
+
+    // The rank of a tree with exactly these labels, or None if it is not one of the trees
+    // enumerated.
+    pub fn rank(&self, t: &Phylo<T>) -> Option<u128>
+        where T: Hash + Eq {
+
+        let n = self.labels.len();
+        if n == 0 {
+            return None;
+        }
+
+        let positions: HashMap<&T, usize> = self.labels.iter().enumerate().map(|(i, x)| (x, i)).collect();
+
+        let tree = t.to_indexed();
+        let mut growth = Growth {
+            parent: Vec::new(),
+            children: Vec::new(),
+            label: Vec::new(),
+            leaf_of: vec![0; n],
+            root: 0
+        };
+
+        let mut seen = vec![false; n];
+
+        for id in tree.node_ids() {
+            let label = if tree.is_leaf(id) {
+                let x = *positions.get(tree.label(id)?)?;
+                if seen[x] {
+                    return None;
+                }
+                seen[x] = true;
+                Some(x)
+            } else {
+                let num_children = tree.num_children(id);
+                if num_children < 2 || (self.binary && num_children > 2) {
+                    return None;
+                }
+                None
+            };
+
+            growth.add_node(tree.parent(id).map(|p| p.index()), label);
+        }
+
+        if seen.contains(&false) {
+            return None;
+        }
+
+        // Insertions, with the numbers of nodes and internal nodes before each of them.
+        let mut insertions = Vec::with_capacity(n);
+        let mut k = growth.label.iter().filter(|x| x.is_none()).count();
+
+        for i in (1..n).rev() {
+            let insertion = growth.remove_leaf(i);
+            let (nodes, internal) = growth.nodes();
+
+            let choice = match insertion {
+                Insertion::Edge(s) => {
+                    k -= 1;
+                    Insertion::Edge(nodes.iter().position(|&u| u == s).unwrap())
+                },
+                Insertion::Node(p) => Insertion::Node(internal.iter().position(|&u| u == p).unwrap())
+            };
+
+            insertions.push((choice, nodes.len(), k));
+        }
+
+        let rank = insertions.into_iter().rev().enumerate()
+            .map(|(j, (choice, num_nodes, k))| {
+                let i = j + 1;
+                let per_edge = self.counts[i+1][k+1];
+
+                match choice {
+                    Insertion::Edge(e) => e as u128 * per_edge,
+                    Insertion::Node(v) => num_nodes as u128 * per_edge + v as u128 * self.counts[i+1][k]
+                }
+            })
+            .sum();
+
+        Some(rank)
+    }
+}
+
+impl<T: Clone> Iterator for LabelledTrees<T> {
+    type Item = Phylo<T>;
+
+    fn next(&mut self) -> Option<Phylo<T>> {
+        if self.next < self.num_trees() {
+            self.next += 1;
+            Some(self.unrank(self.next - 1))
+        } else {
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.num_trees() - self.next;
+
+        if left > usize::MAX as u128 {
+            (usize::MAX, None)
+        } else {
+            (left as usize, Some(left as usize))
+        }
+    }
+}
+
+
+impl<T: Clone> Phylo<T> {
+    pub fn labelled_binary_trees(labels: &[T]) -> LabelledTrees<T> {
+        LabelledTrees::binary(labels)
+    }
+
+    pub fn labelled_trees(labels: &[T]) -> LabelledTrees<T> {
+        LabelledTrees::all(labels)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use phylo::Phylo;
+    use super::LabelledTrees;
+
+    fn reversed(t: &Phylo<u32>) -> Phylo<u32> {
+        t.cata(|&x| Phylo::leaf_with(x), |_, chs| {
+            let mut ts: Vec<Phylo<u32>> = chs.collect();
+            ts.reverse();
+            Phylo::node(ts)
+        })
+    }
+
+    #[test]
+    fn unrank_inverts_rank() {
+        // (2n-3)!! binary trees and OEIS A000311 trees with any multifurcations.
+        let binary = [1, 1, 3, 15, 105, 945];
+        let all = [1, 1, 4, 26, 236, 2752];
+
+        for n in 1..7 {
+            let labels: Vec<u32> = (0..n as u32).collect();
+
+            for &(ref trees, count) in &[(LabelledTrees::binary(&labels), binary[n - 1]), (LabelledTrees::all(&labels), all[n - 1])] {
+                assert_eq!(trees.num_trees(), count);
+
+                for r in 0..trees.num_trees() {
+                    let t = trees.unrank(r);
+
+                    assert_eq!(trees.rank(&t), Some(r));
+                    assert_eq!(trees.unrank(trees.rank(&t).unwrap()), t);
+                    assert_eq!(trees.rank(&reversed(&t)), Some(r));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn trees_outside_the_enumeration_have_no_rank() {
+        let labels = [0u32, 1, 2];
+        let star = Phylo::node(labels.iter().map(|&x| Phylo::leaf_with(x)).collect());
+        let other_labels = Phylo::node(vec![Phylo::leaf_with(0u32), Phylo::node(vec![Phylo::leaf_with(1), Phylo::leaf_with(3)])]);
+
+        assert_eq!(LabelledTrees::binary(&labels).rank(&star), None);
+        assert!(LabelledTrees::all(&labels).rank(&star).is_some());
+        assert_eq!(LabelledTrees::all(&labels).rank(&other_labels), None);
+    }
+
+    #[test]
+    fn trees_are_counted_up_to_the_documented_number_of_labels() {
+        let labels: Vec<u32> = (0..29).collect();
+
+        assert!(LabelledTrees::binary(&labels).num_trees() > 0);
+        assert!(LabelledTrees::all(&labels[..28]).num_trees() > 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "too many trees to count")]
+    fn binary_trees_on_too_many_labels_panic() {
+        let labels: Vec<u32> = (0..30).collect();
+        LabelledTrees::binary(&labels);
+    }
+
+    #[test]
+    #[should_panic(expected = "too many trees to count")]
+    fn trees_on_too_many_labels_panic() {
+        let labels: Vec<u32> = (0..29).collect();
+        LabelledTrees::all(&labels);
+    }
+
+    #[test]
+    fn no_labels_have_no_trees() {
+        let trees = LabelledTrees::<u32>::all(&[]);
+
+        assert_eq!(trees.num_trees(), 0);
+        assert_eq!(trees.rank(&Phylo::leaf_with(0)), None);
+    }
+}
@@ -0,0 +1,160 @@
+use std::iter;
+use std::slice;
+use std::vec;
+
+use phylo::{Phylo, Leaf};
+
+
+// The results of a node's children, in order. Values are moved out of a shared stack, so
+// folding allocates no per-node buffers and places no bounds on the result type.
+pub type Folded<'a, R> = vec::Drain<'a, R>;
+
+pub type ParaFolded<'t, 'a, T, R> = iter::Zip<slice::Iter<'t, Phylo<T>>, Folded<'a, R>>;
+
+pub enum Unfold<T, S> {
+    Leaf(T),
+    Node(Vec<S>)
+}
+
+
+impl<T> Phylo<T> {
+    pub fn cata<R, L, N>(&self, mut leaf: L, mut node: N) -> R
+        where L: FnMut(&T) -> R,
+              N: FnMut(&Self, Folded<R>) -> R {
+
+        if let Leaf(ref x) = *self {
+            return leaf(x);
+        }
+
+        let mut values = Vec::new();
+        let mut stack = vec![(self, self.children().iter())];
+
+        loop {
+            let next = stack.last_mut().unwrap().1.next();
+
+            match next {
+                Some(Leaf(x)) => values.push(leaf(x)),
+
+                Some(ch) => stack.push((ch, ch.children().iter())),
+
+                None => {
+                    let (t, _) = stack.pop().unwrap();
+                    let start = values.len() - t.children().len();
+                    let r = node(t, values.drain(start..));
+
+                    if stack.is_empty() {
+                        return r;
+                    }
+
+                    values.push(r);
+                }
+            }
+        }
+    }
+
+    pub fn para<R, L, N>(&self, leaf: L, mut node: N) -> R
+        where L: FnMut(&T) -> R,
+              N: FnMut(&Self, ParaFolded<T, R>) -> R {
+
+        self.cata(leaf, |t, rs| node(t, t.children().iter().zip(rs)))
+    }
+
+    pub fn fold_inherited<A, R, D, L, N>(&self, root: A, mut down: D, mut leaf: L, mut node: N) -> R
+        where D: FnMut(&A, &Self) -> A,
+              L: FnMut(&T, &A) -> R,
+              N: FnMut(&Self, &A, Folded<R>) -> R {
+
+        if let Leaf(ref x) = *self {
+            return leaf(x, &root);
+        }
+
+        let mut values = Vec::new();
+        let mut stack = vec![(self, self.children().iter(), root)];
+
+        loop {
+            let next = {
+                let top = stack.last_mut().unwrap();
+                top.1.next().map(|ch| (ch, down(&top.2, ch)))
+            };
+
+            match next {
+                Some((Leaf(x), a)) => values.push(leaf(x, &a)),
+
+                Some((ch, a)) => stack.push((ch, ch.children().iter(), a)),
+
+                None => {
+                    let (t, _, a) = stack.pop().unwrap();
+                    let start = values.len() - t.children().len();
+                    let r = node(t, &a, values.drain(start..));
+
+                    if stack.is_empty() {
+                        return r;
+                    }
+
+                    values.push(r);
+                }
+            }
+        }
+    }
+
+    // Attributes of every node in pre-order, each computed from its parent's attribute.
+    pub fn inherit<A, F>(&self, root: A, mut f: F) -> Vec<A>
+        where F: FnMut(&A, &Self) -> A {
+
+        let mut attrs = vec![root];
+        let mut stack: Vec<(&Self, usize)> = self.children().iter().rev().map(|ch| (ch, 0)).collect();
+
+        while let Some((t, parent)) = stack.pop() {
+            let a = f(&attrs[parent], t);
+            let i = attrs.len();
+            attrs.push(a);
+
+            stack.extend(t.children().iter().rev().map(|ch| (ch, i)));
+        }
+
+        attrs
+    }
+
+    pub fn unfold<S, F>(seed: S, mut f: F) -> Self
+        where F: FnMut(S) -> Unfold<T, S> {
+
+        let mut stack: Vec<(vec::IntoIter<S>, Vec<Self>)> = Vec::new();
+        let mut step = f(seed);
+
+        loop {
+            let mut done = match step {
+                Unfold::Leaf(x) => Leaf(x),
+
+                Unfold::Node(seeds) => {
+                    assert!(!seeds.is_empty());
+
+                    let n = seeds.len();
+                    let mut seeds = seeds.into_iter();
+                    let first = seeds.next().unwrap();
+
+                    stack.push((seeds, Vec::with_capacity(n)));
+                    step = f(first);
+                    continue;
+                }
+            };
+
+            loop {
+                match stack.last_mut() {
+                    None => return done,
+
+                    Some(top) => {
+                        top.1.push(done);
+
+                        if let Some(s) = top.0.next() {
+                            step = f(s);
+                            break;
+                        }
+                    }
+                }
+
+                let (_, ts) = stack.pop().unwrap();
+                done = Phylo::node(ts);
+            }
+        }
+    }
+}
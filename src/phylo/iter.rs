@@ -0,0 +1,252 @@
+use std::collections::VecDeque;
+use std::iter::Enumerate;
+use std::slice;
+
+use phylo::Phylo;
+
+
+type ChildIter<'a, T> = slice::Iter<'a, Phylo<T>>;
+
+
+// A node together with its depth.
+#[derive(Clone, Debug)]
+pub struct Visit<'a, T: 'a> {
+    pub node: &'a Phylo<T>,
+    pub depth: u32
+}
+
+
+pub struct PreOrder<'a, T: 'a> {
+    root: Option<&'a Phylo<T>>,
+    stack: Vec<ChildIter<'a, T>>
+}
+
+impl<'a, T> Iterator for PreOrder<'a, T> {
+    type Item = Visit<'a, T>;
+
+    fn next(&mut self) -> Option<Visit<'a, T>> {
+        if let Some(root) = self.root.take() {
+            self.stack.push(root.children().iter());
+
+            return Some(Visit { node: root, depth: 0 });
+        }
+
+        loop {
+            let next = self.stack.last_mut()?.next();
+
+            match next {
+                None => { self.stack.pop(); },
+
+                Some(t) => {
+                    let visit = Visit { node: t, depth: self.stack.len() as u32 };
+                    self.stack.push(t.children().iter());
+
+                    return Some(visit);
+                }
+            }
+        }
+    }
+}
+
+
+pub struct PostOrder<'a, T: 'a> {
+    stack: Vec<(&'a Phylo<T>, ChildIter<'a, T>)>
+}
+
+impl<'a, T> Iterator for PostOrder<'a, T> {
+    type Item = Visit<'a, T>;
+
+    fn next(&mut self) -> Option<Visit<'a, T>> {
+        loop {
+            let next = self.stack.last_mut()?.1.next();
+
+            match next {
+                Some(t) => {
+                    self.stack.push((t, t.children().iter()));
+                },
+
+                None => {
+                    let (t, _) = self.stack.pop().unwrap();
+                    return Some(Visit { node: t, depth: self.stack.len() as u32 });
+                }
+            }
+        }
+    }
+}
+
+
+// Pre-order with the child indices leading to every node from the root. Each path is an owned
+// copy; find_map_with_path lends them instead.
+pub struct PathsPreOrder<'a, T: 'a> {
+    root: Option<&'a Phylo<T>>,
+    stack: Vec<Enumerate<ChildIter<'a, T>>>,
+    path: Vec<usize>
+}
+
+impl<'a, T> Iterator for PathsPreOrder<'a, T> {
+    type Item = (Visit<'a, T>, Vec<usize>);
+
+    fn next(&mut self) -> Option<(Visit<'a, T>, Vec<usize>)> {
+        if let Some(root) = self.root.take() {
+            self.stack.push(root.children().iter().enumerate());
+
+            return Some((Visit { node: root, depth: 0 }, Vec::new()));
+        }
+
+        loop {
+            let next = self.stack.last_mut()?.next();
+
+            match next {
+                None => {
+                    self.stack.pop();
+                    self.path.pop();
+                },
+
+                Some((i, t)) => {
+                    self.path.push(i);
+                    self.stack.push(t.children().iter().enumerate());
+
+                    return Some((Visit { node: t, depth: self.path.len() as u32 }, self.path.clone()));
+                }
+            }
+        }
+    }
+}
+
+
+pub struct LevelOrder<'a, T: 'a> {
+    queue: VecDeque<Visit<'a, T>>
+}
+
+impl<'a, T> Iterator for LevelOrder<'a, T> {
+    type Item = Visit<'a, T>;
+
+    fn next(&mut self) -> Option<Visit<'a, T>> {
+        let visit = self.queue.pop_front()?;
+
+        for t in visit.node.children() {
+            self.queue.push_back(Visit { node: t, depth: visit.depth + 1 });
+        }
+
+        Some(visit)
+    }
+}
+
+
+impl<T> Phylo<T> {
+    pub fn pre_order(&self) -> PreOrder<'_, T> {
+        PreOrder {
+            root: Some(self),
+            stack: Vec::new()
+        }
+    }
+
+    pub fn pre_order_with_paths(&self) -> PathsPreOrder<'_, T> {
+        PathsPreOrder {
+            root: Some(self),
+            stack: Vec::new(),
+            path: Vec::new()
+        }
+    }
+
+    pub fn post_order(&self) -> PostOrder<'_, T> {
+        PostOrder {
+            stack: vec![(self, self.children().iter())]
+        }
+    }
+
+    pub fn level_order(&self) -> LevelOrder<'_, T> {
+        let mut queue = VecDeque::new();
+        queue.push_back(Visit { node: self, depth: 0 });
+
+        LevelOrder { queue }
+    }
+
+    // Visits the nodes in pre-order with the child indices leading to them from the root,
+    // until `f` returns a value. The path is lent to `f` rather than copied for every node.
+    pub fn find_map_with_path<R, F>(&self, mut f: F) -> Option<R>
+        where F: FnMut(Visit<'_, T>, &[usize]) -> Option<R> {
+
+        let mut path = Vec::new();
+
+        if let Some(r) = f(Visit { node: self, depth: 0 }, &path) {
+            return Some(r);
+        }
+
+        let mut stack = vec![self.children().iter().enumerate()];
+
+        while let Some(chs) = stack.last_mut() {
+            match chs.next() {
+                None => {
+                    stack.pop();
+                    path.pop();
+                },
+
+                Some((i, t)) => {
+                    path.push(i);
+
+                    if let Some(r) = f(Visit { node: t, depth: path.len() as u32 }, &path) {
+                        return Some(r);
+                    }
+
+                    stack.push(t.children().iter().enumerate());
+                }
+            }
+        }
+
+        None
+    }
+
+    pub fn for_each_with_path<F>(&self, mut f: F)
+        where F: FnMut(Visit<'_, T>, &[usize]) {
+
+        self.find_map_with_path(|v, path| -> Option<()> {
+            f(v, path);
+            None
+        });
+    }
+
+    pub fn get_subtree(&self, path: &[usize]) -> Option<&Self> {
+        path.iter().try_fold(self, |t, &i| t.children().get(i))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use phylo::Phylo;
+
+    #[test]
+    fn paths_lead_to_the_nodes_in_pre_order() {
+        let t = Phylo::node(vec![
+            Phylo::node(vec![Phylo::leaf_with(0u32), Phylo::leaf_with(1), Phylo::leaf_with(2)]),
+            Phylo::leaf_with(3),
+            Phylo::node(vec![Phylo::leaf_with(4), Phylo::node(vec![Phylo::leaf_with(5), Phylo::leaf_with(6)])])
+        ]);
+
+        let visits: Vec<_> = t.pre_order_with_paths().collect();
+        let paths: Vec<Vec<usize>> = visits.iter().map(|(_, path)| path.clone()).collect();
+
+        assert_eq!(paths, vec![
+            vec![], vec![0], vec![0, 0], vec![0, 1], vec![0, 2], vec![1],
+            vec![2], vec![2, 0], vec![2, 1], vec![2, 1, 0], vec![2, 1, 1]
+        ]);
+
+        for ((visit, path), pre) in visits.iter().zip(t.pre_order()) {
+            assert!(::std::ptr::eq(visit.node, pre.node));
+            assert!(::std::ptr::eq(visit.node, t.get_subtree(path).unwrap()));
+            assert_eq!(visit.depth as usize, path.len());
+            assert_eq!(visit.depth, pre.depth);
+        }
+    }
+
+    #[test]
+    fn a_leaf_is_visited_once_with_an_empty_path() {
+        let t = Phylo::leaf_with(0u32);
+        let paths: Vec<Vec<usize>> = t.pre_order_with_paths().map(|(_, path)| path).collect();
+
+        assert_eq!(paths, vec![Vec::<usize>::new()]);
+        assert_eq!(t.post_order().count(), 1);
+        assert_eq!(t.level_order().count(), 1);
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::mem;
+
+use phylo::Phylo;
+use phylo::arena::{IndexedTree, NodeId};
+
+
+// Euler tour of an IndexedTree with a sparse table of range minima over it, answering lowest
+// common ancestor queries in constant time after O(n log n) preprocessing.
+pub struct Lca<T> {
+    first: Vec<usize>,
+    euler: Vec<NodeId>,
+    depths: Vec<u32>,
+    root_distances: Vec<f64>,
+    table: Vec<Vec<u32>>,
+    leaves: HashMap<T, NodeId>
+}
+
+impl<T: Clone + Hash + Eq> Lca<T> {
+    pub fn new(tree: &IndexedTree<T>) -> Self {
+        let n = tree.len();
+
+        let mut first = vec![0; n];
+        let mut euler = Vec::with_capacity(2*n);
+
+        let mut stack = vec![(tree.root(), tree.first_child(tree.root()))];
+        first[0] = 0;
+        euler.push(tree.root());
+
+        while let Some(top) = stack.last_mut() {
+            match top.1 {
+                Some(ch) => {
+                    top.1 = tree.next_sibling(ch);
+
+                    first[ch.index()] = euler.len();
+                    euler.push(ch);
+                    stack.push((ch, tree.first_child(ch)));
+                },
+
+                None => {
+                    stack.pop();
+
+                    if let Some(&(p, _)) = stack.last() {
+                        euler.push(p);
+                    }
+                }
+            }
+        }
+
+        let depths: Vec<u32> = tree.node_ids().map(|id| tree.depth(id)).collect();
+
+        let mut table: Vec<Vec<u32>> = vec![(0..euler.len() as u32).collect()];
+        let mut width = 1;
+
+        while 2*width <= euler.len() {
+            let next: Vec<u32> = {
+                let prev = table.last().unwrap();
+
+                (0 .. euler.len() + 1 - 2*width)
+                    .map(|i| {
+                        let (a, b) = (prev[i], prev[i + width]);
+
+                        if depths[euler[a as usize].index()] <= depths[euler[b as usize].index()] { a } else { b }
+                    })
+                    .collect()
+            };
+
+            table.push(next);
+            width *= 2;
+        }
+
+        let leaves = tree.leaves()
+            .filter_map(|id| tree.label(id).map(|x| (x.clone(), id)))
+            .collect();
+
+        Lca {
+            first,
+            euler,
+            depths,
+            root_distances: tree.root_distances(),
+            table,
+            leaves
+        }
+    }
+
+    pub fn leaf(&self, label: &T) -> Option<NodeId> {
+        self.leaves.get(label).cloned()
+    }
+
+    pub fn depth(&self, id: NodeId) -> u32 {
+        self.depths[id.index()]
+    }
+
+    pub fn root_distance(&self, id: NodeId) -> f64 {
+        self.root_distances[id.index()]
+    }
+
+    pub fn lca(&self, u: NodeId, v: NodeId) -> NodeId {
+        let (mut i, mut j) = (self.first[u.index()], self.first[v.index()]);
+        if i > j {
+            mem::swap(&mut i, &mut j);
+        }
+
+        let len = j - i + 1;
+        let k = (usize::BITS - 1 - len.leading_zeros()) as usize;
+
+        let (a, b) = (self.table[k][i], self.table[k][j + 1 - (1 << k)]);
+        let (a, b) = (self.euler[a as usize], self.euler[b as usize]);
+
+        if self.depth(a) <= self.depth(b) { a } else { b }
+    }
+
+    pub fn is_ancestor(&self, u: NodeId, v: NodeId) -> bool {
+        self.lca(u, v) == u
+    }
+
+    pub fn path_length(&self, u: NodeId, v: NodeId) -> u32 {
+        self.depth(u) + self.depth(v) - 2*self.depth(self.lca(u, v))
+    }
+
+    // Sum of the branch lengths along the path, counting edges without a length as one.
+    pub fn distance(&self, u: NodeId, v: NodeId) -> f64 {
+        self.root_distance(u) + self.root_distance(v) - 2.0*self.root_distance(self.lca(u, v))
+    }
+
+    pub fn lca_of_leaves(&self, x: &T, y: &T) -> Option<NodeId> {
+        Some(self.lca(self.leaf(x)?, self.leaf(y)?))
+    }
+
+    pub fn path_length_between_leaves(&self, x: &T, y: &T) -> Option<u32> {
+        Some(self.path_length(self.leaf(x)?, self.leaf(y)?))
+    }
+
+    pub fn distance_between_leaves(&self, x: &T, y: &T) -> Option<f64> {
+        Some(self.distance(self.leaf(x)?, self.leaf(y)?))
+    }
+}
+
+impl<T: Clone + Hash + Eq> Phylo<T> {
+    // Node ids are the positions of the nodes in pre-order.
+    pub fn lca_index(&self) -> Lca<T> {
+        Lca::new(&self.to_indexed())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    use phylo::{IndexedTree, LabelledTrees, NodeId, Phylo};
+    use super::Lca;
+
+    // The deepest common node of the paths from the root.
+    fn brute_force_lca(t: &IndexedTree<u32>, u: NodeId, v: NodeId) -> NodeId {
+        let path = |x: NodeId| {
+            let mut p: Vec<NodeId> = t.ancestors(x).collect();
+            p.reverse();
+            p.push(x);
+            p
+        };
+
+        path(u).into_iter().zip(path(v)).take_while(|(a, b)| a == b).last().unwrap().0
+    }
+
+    #[test]
+    fn lcas_match_brute_force() {
+        let mut rng = StdRng::seed_from_u64(33);
+        let labels: Vec<u32> = (0..9).collect();
+        let trees = LabelledTrees::all(&labels);
+
+        for _ in 0..20 {
+            let t = trees.unrank(rng.random_range(0..trees.num_trees())).to_indexed();
+            let lca = Lca::new(&t);
+
+            for u in t.node_ids() {
+                for v in t.node_ids() {
+                    let w = brute_force_lca(&t, u, v);
+
+                    assert_eq!(lca.lca(u, v), w);
+                    assert_eq!(lca.is_ancestor(u, v), w == u);
+                    assert_eq!(lca.path_length(u, v), t.depth(u) + t.depth(v) - 2*t.depth(w));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn leaf_queries() {
+        // ((a, b), (c, (d, e)))
+        let t = Phylo::node(vec![
+            Phylo::node(vec![Phylo::leaf_with('a'), Phylo::leaf_with('b')]),
+            Phylo::node(vec![Phylo::leaf_with('c'), Phylo::node(vec![Phylo::leaf_with('d'), Phylo::leaf_with('e')])])
+        ]);
+        let lca = t.lca_index();
+
+        assert_eq!(lca.leaf(&'c'), Some(NodeId::from_index(5)));
+        assert_eq!(lca.lca_of_leaves(&'d', &'c'), Some(NodeId::from_index(4)));
+        assert_eq!(lca.lca_of_leaves(&'a', &'e'), Some(NodeId::from_index(0)));
+        assert_eq!(lca.path_length_between_leaves(&'a', &'e'), Some(5));
+        assert_eq!(lca.path_length_between_leaves(&'b', &'b'), Some(0));
+        assert_eq!(lca.lca_of_leaves(&'a', &'f'), None);
+        assert_eq!(lca.distance_between_leaves(&'a', &'f'), None);
+    }
+
+    #[test]
+    fn distances_use_branch_lengths() {
+        let mut t = Phylo::node(vec![
+            Phylo::node(vec![Phylo::leaf_with('a'), Phylo::leaf_with('b')]),
+            Phylo::leaf_with('c')
+        ]).to_indexed();
+
+        t.set_length(NodeId::from_index(1), Some(0.5));
+        t.set_length(NodeId::from_index(2), Some(2.0));
+
+        let lca = Lca::new(&t);
+
+        assert_eq!(lca.root_distance(NodeId::from_index(2)), 2.5);
+        assert_eq!(lca.distance_between_leaves(&'a', &'b'), Some(3.0));
+        assert_eq!(lca.distance_between_leaves(&'a', &'c'), Some(3.5));
+    }
+
+    #[test]
+    fn single_leaves_and_deep_trees() {
+        let leaf = Phylo::leaf_with(0u32).lca_index();
+        let x = leaf.leaf(&0).unwrap();
+
+        assert_eq!(leaf.lca(x, x), x);
+        assert_eq!(leaf.path_length(x, x), 0);
+
+        let n = 100_000u32;
+        let t = (1..n).fold(Phylo::leaf_with(0u32), |t, x| Phylo::node(vec![t, Phylo::leaf_with(x)]));
+        let lca = t.lca_index();
+
+        assert_eq!(lca.path_length_between_leaves(&0, &1), Some(2));
+        assert_eq!(lca.path_length_between_leaves(&0, &(n - 1)), Some(n));
+        assert_eq!(lca.lca_of_leaves(&0, &(n - 1)), Some(NodeId::from_index(0)));
+    }
+}
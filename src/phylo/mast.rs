@@ -0,0 +1,442 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use phylo::{Phylo, Unfold};
+use phylo::arena::{IndexedTree, NodeId};
+use phylo::lca::Lca;
+use util::min_cost_assignment;
+
+
+// Sizes of the maximum agreement subtrees of every pair of subtrees of two rooted trees
+// (Steel and Warnow, 1993). Children of multifurcations are paired by a maximum weight
+// matching.
+struct Mast<'t, T: 't> {
+    t1: &'t IndexedTree<T>,
+    t2: &'t IndexedTree<T>,
+    sizes: Vec<u32>
+}
+
+impl<'t, T: Clone + Hash + Eq> Mast<'t, T> {
+    fn new(t1: &'t IndexedTree<T>, t2: &'t IndexedTree<T>) -> Self {
+        let lca1 = Lca::new(t1);
+        let lca2 = Lca::new(t2);
+        let n2 = t2.len();
+
+        let mut mast = Mast {
+            t1,
+            t2,
+            sizes: vec![0; t1.len() * n2]
+        };
+
+        let ids1: Vec<NodeId> = t1.node_ids().collect();
+        let ids2: Vec<NodeId> = t2.node_ids().collect();
+
+        // Children have larger ids than their parents.
+        for &u in ids1.iter().rev() {
+            for &v in ids2.iter().rev() {
+                let size = if t1.is_leaf(u) {
+                    match lca2.leaf(t1.label(u).expect("unlabelled leaf")) {
+                        Some(x) if lca2.is_ancestor(v, x) => 1,
+                        _                                 => 0
+                    }
+                } else if t2.is_leaf(v) {
+                    match lca1.leaf(t2.label(v).expect("unlabelled leaf")) {
+                        Some(x) if lca1.is_ancestor(u, x) => 1,
+                        _                                 => 0
+                    }
+                } else {
+                    let below = t1.children(u).map(|ch| mast.get(ch, v))
+                        .chain(t2.children(v).map(|ch| mast.get(u, ch)))
+                        .max().unwrap();
+
+                    below.max(mast.matching(u, v).0)
+                };
+
+                mast.sizes[u.index()*n2 + v.index()] = size;
+            }
+        }
+
+        mast
+    }
+
+    fn get(&self, u: NodeId, v: NodeId) -> u32 {
+        self.sizes[u.index()*self.t2.len() + v.index()]
+    }
+
+    // The best pairing of the children of u with those of v, and the pairs in it.
+    fn matching(&self, u: NodeId, v: NodeId) -> (u32, Vec<(NodeId, NodeId)>) {
+        let us: Vec<NodeId> = self.t1.children(u).collect();
+        let vs: Vec<NodeId> = self.t2.children(v).collect();
+
+        let swap = us.len() > vs.len();
+        let (rows, cols) = if swap { (&vs, &us) } else { (&us, &vs) };
+
+        let cost: Vec<Vec<i64>> = rows.iter()
+            .map(|&r| cols.iter()
+                .map(|&c| -(if swap { self.get(c, r) } else { self.get(r, c) } as i64))
+                .collect())
+            .collect();
+
+        let (total, assignment) = min_cost_assignment(&cost);
+
+        let pairs = assignment.into_iter().enumerate()
+            .map(|(i, j)| if swap { (cols[j], rows[i]) } else { (rows[i], cols[j]) })
+            .filter(|&(a, b)| self.get(a, b) > 0)
+            .collect();
+
+        (-total as u32, pairs)
+    }
+
+    fn subtree(&self, u: NodeId, v: NodeId) -> Option<Phylo<T>> {
+        if self.get(u, v) == 0 {
+            return None;
+        }
+
+        let tree = Phylo::unfold((u, v), |(mut u, mut v)| {
+            loop {
+                let size = self.get(u, v);
+
+                if self.t1.is_leaf(u) || self.t2.is_leaf(v) {
+                    let x = if self.t1.is_leaf(u) { self.t1.label(u) } else { self.t2.label(v) };
+                    return Unfold::Leaf(x.unwrap().clone());
+                }
+
+                if let Some(ch) = self.t1.children(u).find(|&ch| self.get(ch, v) == size) {
+                    u = ch;
+                } else if let Some(ch) = self.t2.children(v).find(|&ch| self.get(u, ch) == size) {
+                    v = ch;
+                } else {
+                    return Unfold::Node(self.matching(u, v).1);
+                }
+            }
+        });
+
+        Some(tree)
+    }
+}
+
+enum Choice {
+    Leaf(usize),
+    Pair(usize)
+}
+
+// Maximum agreement subtrees of any number of rooted trees. The children of the root of an
+// agreement subtree lie in different children of one node in every tree, found as the lcas
+// in every tree of two leaves a and b in different children of the root. For every such
+// tuple of nodes w(a, b), by the number of leaves under all of its nodes, these leaves are
+// grouped by the children of the nodes they lie in, and the heaviest set of groups lying in
+// different children in every tree is searched for. The best subtree of a group is that of
+// one of its pairs of leaves. Runs in polynomial time for trees of bounded degree, and in time
+// exponential in the degree otherwise.
+struct MultiMast<T> {
+    lcas: Vec<Lca<T>>,
+    // The nodes of every leaf common to all the trees.
+    leaves: Vec<Vec<NodeId>>,
+    labels: Vec<T>,
+    tuples: Vec<Vec<NodeId>>,
+    pairs: Vec<usize>,
+    // The best subtree rooted at every tuple, as its groups.
+    rooted: Vec<(u32, Vec<Vec<NodeId>>)>,
+    // The best subtree of every group, by the lcas of its leaves in every tree.
+    groups: HashMap<Vec<NodeId>, (u32, Choice)>
+}
+
+impl<T: Clone + Hash + Eq> MultiMast<T> {
+    fn new(trees: &[IndexedTree<T>]) -> Self {
+        let lcas: Vec<Lca<T>> = trees.iter().map(Lca::new).collect();
+
+        let (leaves, labels): (Vec<Vec<NodeId>>, Vec<T>) = trees[0].leaves()
+            .filter_map(|id| {
+                let x = trees[0].label(id).expect("unlabelled leaf");
+                let ids: Option<Vec<NodeId>> = lcas.iter().map(|lca| lca.leaf(x)).collect();
+                ids.map(|ids| (ids, x.clone()))
+            })
+            .unzip();
+
+        let n = labels.len();
+
+        let mut mast = MultiMast {
+            lcas,
+            leaves,
+            labels,
+            tuples: Vec::new(),
+            pairs: vec![0; n*n],
+            rooted: Vec::new(),
+            groups: HashMap::new()
+        };
+
+        let mut indices: HashMap<Vec<NodeId>, usize> = HashMap::new();
+        for a in 0..n {
+            for b in a+1..n {
+                let w = mast.lcas_of(&[a, b]);
+                let len = indices.len();
+                let i = *indices.entry(w.clone()).or_insert(len);
+
+                if i == mast.tuples.len() {
+                    mast.tuples.push(w);
+                }
+                mast.pairs[a*n + b] = i;
+                mast.pairs[b*n + a] = i;
+            }
+        }
+
+        let mut order: Vec<(usize, usize)> = mast.tuples.iter().enumerate()
+            .map(|(i, w)| (i, (0..n).filter(|&x| mast.is_under(w, x)).count()))
+            .collect();
+        order.sort_by_key(|&(_, len)| len);
+
+        mast.rooted = vec![(0, Vec::new()); mast.tuples.len()];
+        for (i, _) in order {
+            let w = mast.tuples[i].clone();
+            let xs: Vec<usize> = (0..n).filter(|&x| mast.is_under(&w, x)).collect();
+            mast.rooted[i] = mast.best_rooted(&w, &xs);
+        }
+
+        mast
+    }
+
+    fn lcas_of(&self, xs: &[usize]) -> Vec<NodeId> {
+        self.lcas.iter().enumerate()
+            .map(|(i, lca)| {
+                let mut ids = xs.iter().map(|&x| self.leaves[x][i]);
+                let first = ids.next().unwrap();
+                ids.fold(first, |u, v| lca.lca(u, v))
+            })
+            .collect()
+    }
+
+    fn is_under(&self, w: &[NodeId], x: usize) -> bool {
+        self.lcas.iter().enumerate().all(|(i, lca)| lca.is_ancestor(w[i], self.leaves[x][i]))
+    }
+
+    // Whether x and y lie in different children of w in every tree.
+    fn split(&self, w: &[NodeId], x: usize, y: usize) -> bool {
+        self.lcas.iter().enumerate().all(|(i, lca)| lca.lca(self.leaves[x][i], self.leaves[y][i]) == w[i])
+    }
+
+    fn best_rooted(&mut self, w: &[NodeId], xs: &[usize]) -> (u32, Vec<Vec<NodeId>>) {
+        // Leaves lie in the same group when they are in the same child of w in every tree.
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        for &x in xs {
+            let same = groups.iter().position(|g| {
+                self.lcas.iter().enumerate().all(|(i, lca)| lca.lca(self.leaves[x][i], self.leaves[g[0]][i]) != w[i])
+            });
+
+            match same {
+                Some(j) => groups[j].push(x),
+                None    => groups.push(vec![x])
+            }
+        }
+
+        let keys: Vec<Vec<NodeId>> = groups.iter().map(|g| self.best_of_group(g)).collect();
+        let sizes: Vec<u32> = keys.iter().map(|k| self.groups[k].0).collect();
+
+        let compatible: Vec<Vec<bool>> = groups.iter()
+            .map(|g| groups.iter().map(|h| self.split(w, g[0], h[0])).collect())
+            .collect();
+
+        let mut best = (0, Vec::new());
+        let mut chosen = Vec::new();
+        search(&sizes, &compatible, 0, 0, &mut chosen, &mut best);
+
+        (best.0, best.1.into_iter().map(|j| keys[j].clone()).collect())
+    }
+
+    fn best_of_group(&mut self, xs: &[usize]) -> Vec<NodeId> {
+        let key = self.lcas_of(xs);
+        if self.groups.contains_key(&key) {
+            return key;
+        }
+
+        let n = self.labels.len();
+        let mut best = (1, Choice::Leaf(xs[0]));
+
+        for (i, &a) in xs.iter().enumerate() {
+            for &b in &xs[i+1..] {
+                let w = self.pairs[a*n + b];
+                if self.rooted[w].0 > best.0 {
+                    best = (self.rooted[w].0, Choice::Pair(w));
+                }
+            }
+        }
+
+        self.groups.insert(key.clone(), best);
+        key
+    }
+
+    fn size(&mut self) -> u32 {
+        if self.labels.is_empty() {
+            return 0;
+        }
+
+        let all: Vec<usize> = (0..self.labels.len()).collect();
+        let key = self.best_of_group(&all);
+        self.groups[&key].0
+    }
+
+    fn subtree(&mut self) -> Option<Phylo<T>> {
+        if self.labels.is_empty() {
+            return None;
+        }
+
+        let all: Vec<usize> = (0..self.labels.len()).collect();
+        let key = self.best_of_group(&all);
+
+        let tree = Phylo::unfold(key, |key| match self.groups[&key].1 {
+            Choice::Leaf(x) => Unfold::Leaf(self.labels[x].clone()),
+            Choice::Pair(w) => Unfold::Node(self.rooted[w].1.clone())
+        });
+
+        Some(tree)
+    }
+}
+
+// The heaviest set of at least two pairwise compatible groups, extending `chosen` with groups
+// from `next` on.
+fn search(sizes: &[u32], compatible: &[Vec<bool>], next: usize, total: u32, chosen: &mut Vec<usize>, best: &mut (u32, Vec<usize>)) {
+    if chosen.len() >= 2 && total > best.0 {
+        *best = (total, chosen.clone());
+    }
+
+    let rest: u32 = sizes[next..].iter().sum();
+    if total + rest <= best.0 {
+        return;
+    }
+
+    for j in next..sizes.len() {
+        if chosen.iter().all(|&i| compatible[i][j]) {
+            chosen.push(j);
+            search(sizes, compatible, j + 1, total + sizes[j], chosen, best);
+            chosen.pop();
+        }
+    }
+}
+
+
+impl<T: Clone + Hash + Eq> IndexedTree<T> {
+    pub fn maximum_agreement_subtree_size(&self, other: &Self) -> u32 {
+        Mast::new(self, other).get(self.root(), other.root())
+    }
+
+    // A largest subtree induced by a set of leaves on which both trees agree, or None if they
+    // share no leaves.
+    pub fn maximum_agreement_subtree(&self, other: &Self) -> Option<Phylo<T>> {
+        Mast::new(self, other).subtree(self.root(), other.root())
+    }
+
+    pub fn maximum_agreement_subtree_size_of(trees: &[Self]) -> u32 {
+        match trees {
+            []       => 0,
+            [t1, t2] => t1.maximum_agreement_subtree_size(t2),
+            _        => MultiMast::new(trees).size()
+        }
+    }
+
+    // A largest subtree on which all the trees agree. Two trees are compared by their pairs of
+    // subtrees, and more by the tuples of lcas of their pairs of leaves, which is exponential
+    // in the largest degree.
+    pub fn maximum_agreement_subtree_of(trees: &[Self]) -> Option<Phylo<T>> {
+        match trees {
+            []       => None,
+            [t1, t2] => t1.maximum_agreement_subtree(t2),
+            _        => MultiMast::new(trees).subtree()
+        }
+    }
+}
+
+impl<T: Clone + Hash + Eq> Phylo<T> {
+    pub fn maximum_agreement_subtree_size(&self, other: &Self) -> u32 {
+        self.to_indexed().maximum_agreement_subtree_size(&other.to_indexed())
+    }
+
+    pub fn maximum_agreement_subtree(&self, other: &Self) -> Option<Self> {
+        self.to_indexed().maximum_agreement_subtree(&other.to_indexed())
+    }
+
+    pub fn maximum_agreement_subtree_size_of(trees: &[Self]) -> u32 {
+        let trees: Vec<IndexedTree<T>> = trees.iter().map(Phylo::to_indexed).collect();
+        IndexedTree::maximum_agreement_subtree_size_of(&trees)
+    }
+
+    pub fn maximum_agreement_subtree_of(trees: &[Self]) -> Option<Self> {
+        let trees: Vec<IndexedTree<T>> = trees.iter().map(Phylo::to_indexed).collect();
+        IndexedTree::maximum_agreement_subtree_of(&trees)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    use phylo::{LabelledTrees, Phylo};
+
+    fn canonical(t: &Phylo<u32>) -> Phylo<u32> {
+        t.cata(|&x| Phylo::leaf_with(x), |_, chs| {
+            let mut ts: Vec<Phylo<u32>> = chs.collect();
+            ts.sort();
+            Phylo::node(ts)
+        })
+    }
+
+    fn agree(trees: &[Phylo<u32>], leaves: &[u32]) -> bool {
+        let first = canonical(&trees[0].restrict(leaves));
+        trees[1..].iter().all(|t| canonical(&t.restrict(leaves)) == first)
+    }
+
+    fn brute_force(trees: &[Phylo<u32>], n: u32) -> u32 {
+        (1..1u32 << n)
+            .map(|set| (0..n).filter(|x| set & 1 << x != 0).collect::<Vec<u32>>())
+            .filter(|leaves| agree(trees, leaves))
+            .map(|leaves| leaves.len() as u32)
+            .max().unwrap()
+    }
+
+    #[test]
+    fn agreement_subtrees_of_several_trees_match_brute_force() {
+        let mut rng = StdRng::seed_from_u64(46);
+
+        for n in 1..8u32 {
+            let labels: Vec<u32> = (0..n).collect();
+            let binary = LabelledTrees::binary(&labels);
+            let all = LabelledTrees::all(&labels);
+
+            for i in 0..30 {
+                let k = 1 + i % 4;
+                let family = if i % 2 == 0 { &binary } else { &all };
+                let trees: Vec<Phylo<u32>> = (0..k)
+                    .map(|_| family.unrank(rng.random_range(0..family.num_trees())))
+                    .collect();
+
+                let size = brute_force(&trees, n);
+                assert_eq!(Phylo::maximum_agreement_subtree_size_of(&trees), size);
+
+                let mast = Phylo::maximum_agreement_subtree_of(&trees).unwrap();
+                let leaves: Vec<u32> = mast.get_leaves().into_iter().cloned().collect();
+                assert_eq!(leaves.len() as u32, size);
+                assert!(agree(&trees, &leaves));
+                assert_eq!(canonical(&trees[0].restrict(&leaves)), canonical(&mast));
+            }
+        }
+    }
+
+    #[test]
+    fn agreement_subtree_of_no_trees_is_none() {
+        let trees: Vec<Phylo<u32>> = Vec::new();
+
+        assert_eq!(Phylo::maximum_agreement_subtree_size_of(&trees), 0);
+        assert_eq!(Phylo::maximum_agreement_subtree_of(&trees), None);
+    }
+
+    #[test]
+    fn agreement_subtree_of_disjoint_trees_is_none() {
+        let trees = vec![
+            Phylo::node(vec![Phylo::leaf_with(0u32), Phylo::leaf_with(1)]),
+            Phylo::node(vec![Phylo::leaf_with(1), Phylo::leaf_with(2)]),
+            Phylo::node(vec![Phylo::leaf_with(2), Phylo::leaf_with(0)])
+        ];
+
+        assert_eq!(Phylo::maximum_agreement_subtree_of(&trees), None);
+    }
+}
@@ -0,0 +1,281 @@
+use std::hash::Hash;
+
+use phylo::Phylo;
+use phylo::arena::{IndexedTree, NodeId};
+use phylo::lca::Lca;
+
+
+// A square matrix indexed by leaf labels, with rows in increasing label order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LeafMatrix<T, V> {
+    labels: Vec<T>,
+    values: Vec<V>
+}
+
+impl<T: Ord, V: Copy> LeafMatrix<T, V> {
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    pub fn labels(&self) -> &[T] {
+        &self.labels
+    }
+
+    pub fn index_of(&self, x: &T) -> Option<usize> {
+        self.labels.binary_search(x).ok()
+    }
+
+    pub fn at(&self, i: usize, j: usize) -> V {
+        self.values[i*self.labels.len() + j]
+    }
+
+    pub fn get(&self, x: &T, y: &T) -> Option<V> {
+        Some(self.at(self.index_of(x)?, self.index_of(y)?))
+    }
+
+    pub fn row(&self, i: usize) -> &[V] {
+        let n = self.labels.len();
+        &self.values[i*n .. (i+1)*n]
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item=(&T, &[V])> {
+        self.labels.iter().enumerate().map(move |(i, x)| (x, self.row(i)))
+    }
+
+    // Entries above the diagonal, row by row.
+    pub fn upper_triangle(&self) -> impl Iterator<Item=V> + '_ {
+        let n = self.labels.len();
+        (0..n).flat_map(move |i| self.row(i)[i+1..].iter().cloned())
+    }
+
+    // Entries on or above the diagonal, row by row.
+    pub fn upper_triangle_with_diagonal(&self) -> impl Iterator<Item=V> + '_ {
+        let n = self.labels.len();
+        (0..n).flat_map(move |i| self.row(i)[i..].iter().cloned())
+    }
+}
+
+impl<T: Ord, V: Copy + Into<f64>> LeafMatrix<T, V> {
+    // L^p distance between the vectors of entries above (or on, with `diagonal`) the diagonal
+    // of two matrices over the same labels. An infinite `p` gives the maximum difference.
+    pub fn lp_distance(&self, other: &Self, p: f64, diagonal: bool) -> f64 {
+        assert!(self.labels == other.labels, "trees have different leaf sets");
+        assert!(p >= 1.0, "p must be at least 1");
+
+        let diffs: Vec<f64> = if diagonal {
+            self.upper_triangle_with_diagonal()
+                .zip(other.upper_triangle_with_diagonal())
+                .map(|(a, b)| (a.into() - b.into()).abs())
+                .collect()
+        } else {
+            self.upper_triangle()
+                .zip(other.upper_triangle())
+                .map(|(a, b)| (a.into() - b.into()).abs())
+                .collect()
+        };
+
+        if p.is_infinite() {
+            diffs.into_iter().fold(0.0, f64::max)
+        } else {
+            diffs.into_iter().map(|d| d.powf(p)).sum::<f64>().powf(1.0 / p)
+        }
+    }
+}
+
+
+impl<T: Clone + Hash + Ord> IndexedTree<T> {
+    fn leaf_matrix<V, F>(&self, mut f: F) -> LeafMatrix<T, V>
+        where F: FnMut(&Lca<T>, NodeId, NodeId) -> V {
+
+        let lca = Lca::new(self);
+
+        let mut leaves: Vec<(T, NodeId)> = self.leaves()
+            .map(|id| (self.label(id).expect("unlabelled leaf").clone(), id))
+            .collect();
+        leaves.sort_by(|a, b| a.0.cmp(&b.0));
+
+        assert!(leaves.windows(2).all(|w| w[0].0 != w[1].0), "repeated leaf label");
+
+        let mut values = Vec::with_capacity(leaves.len() * leaves.len());
+        for &(_, u) in &leaves {
+            for &(_, v) in &leaves {
+                values.push(f(&lca, u, v));
+            }
+        }
+
+        LeafMatrix {
+            labels: leaves.into_iter().map(|(x, _)| x).collect(),
+            values
+        }
+    }
+
+    // Depth of the lowest common ancestor of each pair of leaves; the diagonal holds the
+    // depths of the leaves themselves.
+    pub fn cophenetic_matrix(&self) -> LeafMatrix<T, u32> {
+        self.leaf_matrix(|lca, u, v| lca.depth(lca.lca(u, v)))
+    }
+
+    pub fn weighted_cophenetic_matrix(&self) -> LeafMatrix<T, f64> {
+        self.leaf_matrix(|lca, u, v| lca.root_distance(lca.lca(u, v)))
+    }
+
+    pub fn path_length_matrix(&self) -> LeafMatrix<T, u32> {
+        self.leaf_matrix(|lca, u, v| lca.path_length(u, v))
+    }
+
+    pub fn distance_matrix(&self) -> LeafMatrix<T, f64> {
+        self.leaf_matrix(|lca, u, v| lca.distance(u, v))
+    }
+
+    // Cardona et al. (2013): L^p distance between the cophenetic vectors of both trees, which
+    // include the depths of the leaves.
+    pub fn cophenetic_distance(&self, other: &Self, p: f64) -> f64 {
+        self.cophenetic_matrix().lp_distance(&other.cophenetic_matrix(), p, true)
+    }
+
+    pub fn weighted_cophenetic_distance(&self, other: &Self, p: f64) -> f64 {
+        self.weighted_cophenetic_matrix().lp_distance(&other.weighted_cophenetic_matrix(), p, true)
+    }
+
+    // L^p distance between the path lengths of every pair of leaves; p = 1 gives the nodal
+    // distance and p = 2 the path-difference distance of Steel and Penny (1993).
+    pub fn nodal_distance(&self, other: &Self, p: f64) -> f64 {
+        self.path_length_matrix().lp_distance(&other.path_length_matrix(), p, false)
+    }
+
+    pub fn weighted_nodal_distance(&self, other: &Self, p: f64) -> f64 {
+        self.distance_matrix().lp_distance(&other.distance_matrix(), p, false)
+    }
+}
+
+impl<T: Clone + Hash + Ord> Phylo<T> {
+    pub fn cophenetic_matrix(&self) -> LeafMatrix<T, u32> {
+        self.to_indexed().cophenetic_matrix()
+    }
+
+    pub fn path_length_matrix(&self) -> LeafMatrix<T, u32> {
+        self.to_indexed().path_length_matrix()
+    }
+
+    pub fn cophenetic_distance(&self, other: &Self, p: f64) -> f64 {
+        self.to_indexed().cophenetic_distance(&other.to_indexed(), p)
+    }
+
+    pub fn nodal_distance(&self, other: &Self, p: f64) -> f64 {
+        self.to_indexed().nodal_distance(&other.to_indexed(), p)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use phylo::{NodeId, Phylo};
+
+    fn leaf(x: char) -> Phylo<char> {
+        Phylo::leaf_with(x)
+    }
+
+    #[test]
+    fn cophenetic_and_path_length_matrices() {
+        // ((c, a, d), b)
+        let t = Phylo::node(vec![Phylo::node(vec![leaf('c'), leaf('a'), leaf('d')]), leaf('b')]);
+
+        let cophenetic = t.cophenetic_matrix();
+        assert_eq!(cophenetic.labels(), &['a', 'b', 'c', 'd']);
+        assert_eq!(cophenetic.row(0), &[2, 0, 1, 1]);
+        assert_eq!(cophenetic.row(1), &[0, 1, 0, 0]);
+        assert_eq!(cophenetic.get(&'c', &'d'), Some(1));
+        assert_eq!(cophenetic.get(&'c', &'e'), None);
+        assert_eq!(cophenetic.upper_triangle().collect::<Vec<_>>(), vec![0, 1, 1, 0, 0, 1]);
+
+        let paths = t.path_length_matrix();
+        assert_eq!(paths.row(0), &[0, 3, 2, 2]);
+        assert_eq!(paths.row(1), &[3, 0, 3, 3]);
+        assert_eq!(paths.upper_triangle_with_diagonal().collect::<Vec<_>>(), vec![0, 3, 2, 2, 0, 3, 3, 0, 2, 0]);
+    }
+
+    #[test]
+    fn weighted_matrices() {
+        // ((a:1, b:2):0.5, c:3)
+        let mut t = Phylo::node(vec![Phylo::node(vec![leaf('a'), leaf('b')]), leaf('c')]).to_indexed();
+        for &(i, l) in &[(1, 0.5), (2, 1.0), (3, 2.0), (4, 3.0)] {
+            t.set_length(NodeId::from_index(i), Some(l));
+        }
+
+        let cophenetic = t.weighted_cophenetic_matrix();
+        assert_eq!(cophenetic.row(0), &[1.5, 0.5, 0.0]);
+        assert_eq!(cophenetic.row(2), &[0.0, 0.0, 3.0]);
+
+        let distances = t.distance_matrix();
+        assert_eq!(distances.row(0), &[0.0, 3.0, 4.5]);
+        assert_eq!(distances.row(1), &[3.0, 0.0, 5.5]);
+    }
+
+    #[test]
+    fn single_leaf_matrices() {
+        let m = leaf('a').cophenetic_matrix();
+
+        assert_eq!(m.len(), 1);
+        assert_eq!(m.at(0, 0), 0);
+        assert_eq!(m.upper_triangle().count(), 0);
+    }
+
+    #[test]
+    fn cophenetic_and_nodal_distances() {
+        // ((a, b), c) and ((a, c), b)
+        let t1 = Phylo::node(vec![Phylo::node(vec![leaf('a'), leaf('b')]), leaf('c')]);
+        let t2 = Phylo::node(vec![Phylo::node(vec![leaf('a'), leaf('c')]), leaf('b')]);
+
+        // The cophenetic vectors are (2, 1, 0, 2, 0, 1) and (2, 0, 1, 1, 0, 2).
+        assert_eq!(t1.cophenetic_distance(&t2, 1.0), 4.0);
+        assert_eq!(t1.cophenetic_distance(&t2, 2.0), 2.0);
+        assert_eq!(t1.cophenetic_distance(&t2, f64::INFINITY), 1.0);
+
+        // The path lengths are (2, 3, 3) and (3, 2, 3).
+        assert_eq!(t1.nodal_distance(&t2, 1.0), 2.0);
+        assert_eq!(t1.nodal_distance(&t2, 2.0), 2f64.sqrt());
+
+        assert_eq!(t1.cophenetic_distance(&t1, 1.0), 0.0);
+        assert_eq!(t1.nodal_distance(&t1, 1.0), 0.0);
+    }
+
+    #[test]
+    fn weighted_distances() {
+        // (a:1, b:1, c:1) and (a:2, b:1, c:4)
+        let t1 = Phylo::node(vec![leaf('a'), leaf('b'), leaf('c')]).to_indexed();
+        let mut t2 = t1.clone();
+        t2.set_length(NodeId::from_index(1), Some(2.0));
+        t2.set_length(NodeId::from_index(3), Some(4.0));
+
+        assert_eq!(t1.weighted_cophenetic_distance(&t2, 1.0), 4.0);
+        assert_eq!(t1.weighted_nodal_distance(&t2, 1.0), 1.0 + 4.0 + 3.0);
+        assert_eq!(t1.nodal_distance(&t2, 1.0), 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "trees have different leaf sets")]
+    fn distances_between_different_leaf_sets_panic() {
+        let t1 = Phylo::node(vec![leaf('a'), leaf('b')]);
+        let t2 = Phylo::node(vec![leaf('a'), leaf('c')]);
+
+        t1.nodal_distance(&t2, 1.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "p must be at least 1")]
+    fn small_exponents_panic() {
+        let t = Phylo::node(vec![leaf('a'), leaf('b')]);
+
+        t.cophenetic_distance(&t, 0.5);
+    }
+
+    #[test]
+    #[should_panic(expected = "repeated leaf label")]
+    fn repeated_labels_panic() {
+        Phylo::node(vec![leaf('a'), leaf('a')]).cophenetic_matrix();
+    }
+}
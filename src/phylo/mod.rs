@@ -1,13 +1,21 @@
 use std::sync::Arc;
 use std::cmp::Ordering;
+use std::fmt;
+use std::mem;
+use std::ops::Deref;
+use std::slice;
 
 
-#[derive(PartialEq, Eq, Clone, Debug)]
+#[derive(Eq, Clone)]
 pub enum Phylo<T> {
     Leaf(T),
-    Node(Arc<[Phylo<T>]>)
+    Node(Children<T>)
 }
 
+// The shared children of a node, which dereference to a slice.
+#[derive(Clone)]
+pub struct Children<T>(Arc<[Phylo<T>]>);
+
 pub use self::Phylo::{Leaf, Node};
 
 #[macro_export]
@@ -21,7 +29,39 @@ macro_rules! make_phylo {
     };
 }
 
+pub mod arena;
+pub mod cluster;
+pub mod consensus;
+pub mod edit;
+pub mod enumerate;
+pub mod fold;
+pub mod iter;
+pub mod lca;
+pub mod mast;
+pub mod matrix;
 pub mod newick;
+pub mod ranked;
+pub mod rearrange;
+pub mod restrict;
+pub mod rspr;
+pub mod rf;
+pub mod triplets;
+pub mod unrooted;
+pub mod zipper;
+
+pub use self::arena::{IndexedTree, NodeId};
+pub use self::cluster::{LeafSet, TaxonIndex};
+pub use self::consensus::{CladeFrequencies, Credibility, CredibleTree, SupportedTree};
+pub use self::enumerate::LabelledTrees;
+pub use self::fold::{Folded, Unfold};
+pub use self::iter::Visit;
+pub use self::lca::Lca;
+pub use self::matrix::LeafMatrix;
+pub use self::ranked::RankedTree;
+pub use self::rf::RobinsonFoulds;
+pub use self::triplets::TopologyComparison;
+pub use self::unrooted::UnrootedTree;
+pub use self::zipper::Zipper;
 
 
 impl<T> Phylo<T> {
@@ -30,126 +70,118 @@ impl<T> Phylo<T> {
     }
 
     pub fn shared_node(children: Arc<[Self]>) -> Self {
-        Node(children)
+        Node(Children(children))
     }
 
     pub fn node(children: Vec<Self>) -> Self {
         Self::shared_node(Arc::from(children.into_boxed_slice()))
     }
 
-    pub fn is_leaf(&self) -> bool {
+    pub fn children(&self) -> &[Self] {
         match self {
-            Leaf(..) => true,
-            Node(..) => false
+            Leaf(..)     => &[],
+            Node(ref ts) => ts
         }
     }
 
-    pub fn depth(&self) -> u32 {
+    pub fn is_leaf(&self) -> bool {
         match self {
-            Leaf(..)     => 0,
-            Node(ref ts) => ts.iter().map(|ch| ch.depth()).max().unwrap() + 1
+            Leaf(..) => true,
+            Node(..) => false
         }
     }
 
-    fn get_leaves_depths_plus(&self, rec_depth: u32) -> Vec<u32> {
-        match self {
-            Leaf(..)     => vec![rec_depth],
-            Node(ref ts) => ts.iter().flat_map(|ch| ch.get_leaves_depths_plus(rec_depth+1)).collect()
-        }
+    pub fn depth(&self) -> u32 {
+        self.cata(|_| 0, |_, ds| ds.max().unwrap() + 1)
     }
 
     pub fn get_leaves_depths(&self) -> Vec<u32> {
-        self.get_leaves_depths_plus(0)
-    }
+        let mut depths = Vec::new();
+        let mut stack = vec![(self, 0)];
 
-    pub fn get_leaves<'r>(&'r self) -> Vec<&'r T> {
-        match self {
-            Leaf(ref x)  => {
-                [x].iter().map(|&x| x).collect()
-            },
-            Node(ref ts) => {
-                ts.iter()
-                    .flat_map(|ch| ch.get_leaves())
-                    .collect()
+        while let Some((t, depth)) = stack.pop() {
+            match t {
+                Leaf(..)     => depths.push(depth),
+                Node(ref ts) => stack.extend(ts.iter().rev().map(|ch| (ch, depth+1)))
             }
         }
+
+        depths
     }
 
-    pub fn isomorphic<U>(&self, other: &Phylo<U>) -> bool {
-        match (self, other) {
-            (Leaf(..), Leaf(..)) => true,
-            (Leaf(..), Node(..)) => false,
-            (Node(..), Leaf(..)) => false,
-
-            (Node(ref ts1), Node(ref ts2)) =>
-                ts1.len() == ts2.len() &&
-                    ts1.iter()
-                        .zip(ts2.iter())
-                        .all(|(t1,t2)| t1.isomorphic(t2))
+    pub fn get_leaves(&self) -> Vec<&T> {
+        let mut leaves = Vec::new();
+        let mut stack = vec![self];
+
+        while let Some(t) = stack.pop() {
+            match t {
+                Leaf(ref x)  => leaves.push(x),
+                Node(ref ts) => stack.extend(ts.iter().rev())
+            }
         }
+
+        leaves
     }
 
-    pub fn cmp_shape_grlex(&self, other: &Self) -> Ordering {
-        match (self, other) {
-            (Leaf(..), Leaf(..)) => Ordering::Equal,
-            (Leaf(..), Node(..)) => Ordering::Less,
-            (Node(..), Leaf(..)) => Ordering::Greater,
-
-            (Node(ref ts1), Node(ref ts2)) => {
-                match ts1.len().cmp(&ts2.len()) {
-                    Ordering::Equal => {
-                        for (t1, t2) in ts1.iter().zip(ts2.iter()) {
-                            match t1.cmp_shape_grlex(t2) {
-                                Ordering::Equal => {},
-                                c               => { return c; }
-                            }
-                        }
+    fn cmp_by<U, F>(&self, other: &Phylo<U>, mut cmp_leaves: F) -> Ordering
+        where F: FnMut(&T, &U) -> Ordering {
 
-                        return Ordering::Equal;
+        let mut stack = vec![(slice::from_ref(self).iter(), slice::from_ref(other).iter())];
+
+        while let Some(top) = stack.last_mut() {
+            match (top.0.next(), top.1.next()) {
+                (Some(t1), Some(t2)) => match (t1, t2) {
+                    (Leaf(ref x), Leaf(ref y)) => {
+                        match cmp_leaves(x, y) {
+                            Ordering::Equal => {},
+                            c               => { return c; }
+                        }
                     },
-                    c => c
-                }
+                    (Leaf(..), Node(..)) => { return Ordering::Less; },
+                    (Node(..), Leaf(..)) => { return Ordering::Greater; },
+
+                    (Node(ref ts1), Node(ref ts2)) => {
+                        match ts1.len().cmp(&ts2.len()) {
+                            Ordering::Equal => stack.push((ts1.iter(), ts2.iter())),
+                            c               => { return c; }
+                        }
+                    }
+                },
+
+                _ => { stack.pop(); }
             }
         }
+
+        Ordering::Equal
+    }
+
+    pub fn isomorphic<U>(&self, other: &Phylo<U>) -> bool {
+        self.cmp_by(other, |_, _| Ordering::Equal) == Ordering::Equal
+    }
+
+    pub fn cmp_shape_grlex(&self, other: &Self) -> Ordering {
+        self.cmp_by(other, |_, _| Ordering::Equal)
     }
 
     pub fn binary_fold<R, F>(&self, leaf_value: R, mut f: F) -> R
         where F: FnMut(&Self, R, R) -> R,
-              R: Copy {
+              R: Clone {
 
-        match self {
-            Leaf(..)     => leaf_value,
-
-            Node(ref ts) => {
-                assert_eq!(ts.len(), 2);
-
-                let r0 = {
-                    let f_ref = &mut f as &mut FnMut(&Self, R, R) -> R;
-                    ts[0].binary_fold(leaf_value, f_ref)
-                };
-                let r1 = {
-                    let f_ref = &mut f as &mut FnMut(&Self, R, R) -> R;
-                    ts[1].binary_fold(leaf_value, f_ref)
-                };
-                f(self, r0, r1)
-            }
-        }
+        self.cata(|_| leaf_value.clone(), |t, mut rs| {
+            assert_eq!(rs.len(), 2);
+
+            let r0 = rs.next().unwrap();
+            let r1 = rs.next().unwrap();
+            f(t, r0, r1)
+        })
     }
 
 
     pub fn fold<R, F>(&self, leaf_value: R, f: F) -> R
-        where F: Fn(&Self, &mut Iterator<Item=R>) -> R,
-              R: Copy {
-
-        match self {
-            Leaf(..)     => leaf_value,
-            Node(ref ts) => {
-                let f_ref = &f as &Fn(&Self, &mut Iterator<Item=R>) -> R;
+        where F: FnMut(&Self, Folded<R>) -> R,
+              R: Clone {
 
-                let mut it = ts.iter().map(|t| t.fold(leaf_value, f_ref));
-                f(self, &mut it)
-            }
-        }
+        self.cata(|_| leaf_value.clone(), f)
     }
 }
 
@@ -161,22 +193,146 @@ impl<T> PartialOrd for Phylo<T> where T: Ord {
 
 impl<T> Ord for Phylo<T> where T: Ord {
     fn cmp(&self, other: &Self) -> Ordering {
-        match (self, other) {
-            (Leaf(ref x), Leaf(ref y)) => x.cmp(y),
-            (Leaf(..), Node(..))       => Ordering::Less,
-            (Node(..), Leaf(..))       => Ordering::Greater,
-
-            (Node(ref ts1), Node(ref ts2)) => {
-                match ts1.len().cmp(&ts2.len()) {
-                    Ordering::Equal =>
-                        ts1.iter().zip(ts2.iter())
-                            .map(|(t1, t2)| t1.cmp(t2))
-                            .filter(|&o| o != Ordering::Equal)
-                            .next()
-                            .unwrap_or(Ordering::Equal),
-                    c => c
+        self.cmp_by(other, |x, y| x.cmp(y))
+    }
+}
+
+impl<T> PartialEq for Phylo<T> where T: PartialEq {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp_by(other, |x, y| if x == y { Ordering::Equal } else { Ordering::Less }) == Ordering::Equal
+    }
+}
+
+impl<T> Deref for Children<T> {
+    type Target = [Phylo<T>];
+
+    fn deref(&self) -> &[Phylo<T>] {
+        &self.0
+    }
+}
+
+impl<T> From<Arc<[Phylo<T>]>> for Children<T> {
+    fn from(ts: Arc<[Phylo<T>]>) -> Self {
+        Children(ts)
+    }
+}
+
+// Writes trees as the derived Debug would without its pretty-printing, with an explicit stack
+// so that deep trees do not overflow the call stack. `list` brackets them as a list.
+fn debug_trees<T: fmt::Debug>(ts: &[Phylo<T>], list: bool, f: &mut fmt::Formatter) -> fmt::Result {
+    if list {
+        f.write_str("[")?;
+    }
+
+    let mut stack = vec![(ts.iter(), true)];
+
+    while let Some(top) = stack.last_mut() {
+        match top.0.next() {
+            None => {
+                stack.pop();
+
+                if !stack.is_empty() {
+                    f.write_str("])")?;
+                } else if list {
+                    f.write_str("]")?;
+                }
+            },
+
+            Some(t) => {
+                if !mem::replace(&mut top.1, false) {
+                    f.write_str(", ")?;
+                }
+
+                match *t {
+                    Leaf(ref x) => write!(f, "Leaf({:?})", x)?,
+                    Node(ref chs) => {
+                        f.write_str("Node([")?;
+                        stack.push((chs.iter(), true));
+                    }
                 }
             }
         }
     }
+
+    Ok(())
+}
+
+impl<T: fmt::Debug> fmt::Debug for Phylo<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        debug_trees(slice::from_ref(self), false, f)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Children<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        debug_trees(&self.0, true, f)
+    }
+}
+
+impl<T: PartialEq> PartialEq for Children<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<T: Eq> Eq for Children<T> {}
+
+// Moves the children lists owned only by `ts` to `detached`, leaving empty ones in their place.
+fn detach_unique_children<T>(ts: &mut Arc<[Phylo<T>]>,
+                             detached: &mut Vec<Arc<[Phylo<T>]>>,
+                             empty: &mut Option<Arc<[Phylo<T>]>>) {
+
+    if let Some(ts) = Arc::get_mut(ts) {
+        for t in ts.iter_mut() {
+            if let Node(Children(ref mut chs)) = *t {
+                if !chs.is_empty() && Arc::get_mut(chs).is_some() {
+                    let placeholder = empty.get_or_insert_with(|| Arc::new([])).clone();
+                    detached.push(mem::replace(chs, placeholder));
+                }
+            }
+        }
+    }
+}
+
+// Subtrees owned only by the children being dropped are moved to an explicit stack before
+// being released, so that dropping a deep tree does not recurse once per level.
+impl<T> Drop for Children<T> {
+    fn drop(&mut self) {
+        let mut detached = Vec::new();
+        let mut empty = None;
+
+        detach_unique_children(&mut self.0, &mut detached, &mut empty);
+
+        while let Some(mut ts) = detached.pop() {
+            detach_unique_children(&mut ts, &mut detached, &mut empty);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use phylo::Phylo;
+
+    #[test]
+    fn debug_matches_the_derived_format() {
+        let t = Phylo::node(vec![
+            Phylo::leaf_with("a"),
+            Phylo::node(vec![Phylo::leaf_with("b"), Phylo::node(vec![Phylo::leaf_with("c")])])
+        ]);
+
+        assert_eq!(format!("{:?}", t), r#"Node([Leaf("a"), Node([Leaf("b"), Node([Leaf("c")])])])"#);
+        assert_eq!(format!("{:?}", Phylo::leaf_with(1)), "Leaf(1)");
+        assert_eq!(format!("{:?}", Phylo::<u32>::node(vec![])), "Node([])");
+    }
+
+    #[test]
+    fn debug_of_a_deep_caterpillar_does_not_overflow() {
+        let n = 100_000;
+        let t = (1..n).fold(Phylo::leaf_with(0u32), |t, x| Phylo::node(vec![t, Phylo::leaf_with(x)]));
+
+        let s = format!("{:?}", t);
+        assert!(s.starts_with(&"Node([".repeat(n as usize - 1)));
+        assert!(s.ends_with("Leaf(99999)])"));
+    }
 }
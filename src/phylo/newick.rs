@@ -1,4 +1,5 @@
 use std::fmt;
+use std::slice;
 use phylo::{Phylo, Leaf, Node};
 
 
@@ -13,11 +14,11 @@ impl<'a, T: ToNewick> fmt::Display for AsNewick<'a, T> {
 }
 
 pub trait ToNewickLeaf {
-    fn fmt_newick_leaf_str(&self, &mut fmt::Formatter) -> fmt::Result;
+    fn fmt_newick_leaf_str(&self, f: &mut fmt::Formatter) -> fmt::Result;
 }
 
 pub trait ToNewick: Sized {
-    fn fmt_newick(&self, &mut fmt::Formatter) -> fmt::Result;
+    fn fmt_newick(&self, f: &mut fmt::Formatter) -> fmt::Result;
 
     fn to_newick(&self) -> String {
 
@@ -47,22 +48,36 @@ impl ToNewickLeaf for str {
 
 impl<T: ToNewickLeaf> ToNewick for Phylo<T> {
     fn fmt_newick(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Leaf(ref x)  => x.fmt_newick_leaf_str(f),
-
-            Node(ref ts) => {
-                try!(f.write_str("("));
-                try!(ts[0].fmt_newick(f));
-
-                for t in ts[1..].iter() {
-                    try!(f.write_str(","));
-                    try!(t.fmt_newick(f))
+        let mut stack = vec![(slice::from_ref(self).iter(), true)];
+
+        while let Some(top) = stack.last_mut() {
+            match top.0.next() {
+                None => {
+                    stack.pop();
+
+                    if !stack.is_empty() {
+                        f.write_str(")")?;
+                    }
+                },
+
+                Some(t) => {
+                    if !top.1 {
+                        f.write_str(",")?;
+                    }
+                    top.1 = false;
+
+                    match t {
+                        Leaf(ref x)  => x.fmt_newick_leaf_str(f)?,
+
+                        Node(ref ts) => {
+                            f.write_str("(")?;
+                            stack.push((ts.iter(), true));
+                        }
+                    }
                 }
-
-                f.write_str(")")
             }
         }
-    }
 
+        Ok(())
+    }
 }
-
@@ -0,0 +1,185 @@
+use rand::Rng;
+
+use phylo::{Phylo, Leaf, Node, Unfold};
+use phylo::arena::{IndexedTree, NodeId};
+use util::gcd;
+
+
+// A tree with its internal nodes ordered in time. The root has rank 0 and every later event
+// the next rank. Children are sorted by the ranks of internal nodes, followed by the leaves.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RankedTree<T> {
+    tree: Phylo<T>,
+    ranks: Vec<usize>
+}
+
+pub(crate) enum Part<T> {
+    Event(usize),
+    Leaf(T)
+}
+
+impl<T: Clone> RankedTree<T> {
+    // Builds a tree from the children of every event, in order of rank.
+    pub(crate) fn assemble(events: &[Vec<Part<T>>]) -> Self {
+        let mut ranks = Vec::with_capacity(events.len());
+
+        // Nodes are unfolded in pre-order.
+        let tree = Phylo::unfold(Part::Event(0), |part| match part {
+            Part::Leaf(x)  => Unfold::Leaf(x),
+            Part::Event(r) => {
+                ranks.push(r);
+
+                let mut chs: Vec<usize> = events[r].iter()
+                    .filter_map(|p| match *p { Part::Event(s) => Some(s), _ => None })
+                    .collect();
+                chs.sort();
+
+                let leaves = events[r].iter().filter_map(|p| match *p { Part::Leaf(ref x) => Some(x.clone()), _ => None });
+                Unfold::Node(chs.into_iter().map(Part::Event).chain(leaves.map(Part::Leaf)).collect())
+            }
+        });
+
+        RankedTree {
+            tree,
+            ranks
+        }
+    }
+
+    // Ranks the internal nodes of a tree by their times, given for every node in pre-order
+    // and increasing towards the leaves. Events at the same time are ranked in pre-order.
+    pub fn from_times(t: &Phylo<T>, times: &[f64]) -> Self {
+        RankedTree::from_indexed_times(&t.to_indexed(), times)
+    }
+
+    pub fn from_indexed_times(tree: &IndexedTree<T>, times: &[f64]) -> Self {
+        assert_eq!(times.len(), tree.len(), "one time per node");
+        assert!(!tree.is_leaf(tree.root()), "no events in a single leaf");
+
+        let mut internal: Vec<NodeId> = tree.node_ids().filter(|&id| !tree.is_leaf(id)).collect();
+        internal.sort_by(|a, b| times[a.index()].partial_cmp(&times[b.index()]).unwrap().then(a.cmp(b)));
+
+        let mut rank = vec![0; tree.len()];
+        for (r, &id) in internal.iter().enumerate() {
+            rank[id.index()] = r;
+        }
+
+        let events: Vec<Vec<Part<T>>> = internal.iter()
+            .map(|&id| tree.children(id)
+                .map(|ch| {
+                    assert!(times[ch.index()] >= times[id.index()], "node earlier than its parent");
+
+                    if tree.is_leaf(ch) {
+                        Part::Leaf(tree.label(ch).expect("unlabelled leaf").clone())
+                    } else {
+                        Part::Event(rank[ch.index()])
+                    }
+                })
+                .collect())
+            .collect();
+
+        RankedTree::assemble(&events)
+    }
+
+    // A random binary tree from the Kingman coalescent, which is a uniform labelled history.
+    pub fn random_coalescent<R: Rng>(labels: &[T], rng: &mut R) -> Self {
+        assert!(labels.len() > 1, "no events with fewer than two leaves");
+
+        let n = labels.len();
+        let mut lineages: Vec<Part<T>> = labels.iter().cloned().map(Part::Leaf).collect();
+        let mut events: Vec<Vec<Part<T>>> = (0..n-1).map(|_| Vec::new()).collect();
+
+        for r in (0..n-1).rev() {
+            let i = rng.random_range(0..lineages.len());
+            let a = lineages.swap_remove(i);
+            let j = rng.random_range(0..lineages.len());
+            let b = lineages.swap_remove(j);
+
+            events[r] = vec![a, b];
+            lineages.push(Part::Event(r));
+        }
+
+        RankedTree::assemble(&events)
+    }
+}
+
+impl<T> RankedTree<T> {
+    pub fn tree(&self) -> &Phylo<T> {
+        &self.tree
+    }
+
+    // The ranks of the internal nodes, in pre-order.
+    pub fn ranks(&self) -> &[usize] {
+        &self.ranks
+    }
+
+    pub fn num_events(&self) -> usize {
+        self.ranks.len()
+    }
+
+    pub fn num_leaves(&self) -> usize {
+        self.tree.get_leaves().len()
+    }
+
+    pub fn is_binary(&self) -> bool {
+        self.tree.pre_order().all(|v| v.node.is_leaf() || v.node.children().len() == 2)
+    }
+
+    // The children of every event, by rank: the ranks of the internal ones and the number of
+    // leaves.
+    pub fn events(&self) -> Vec<(Vec<usize>, usize)> {
+        let mut events = vec![(Vec::new(), 0); self.ranks.len()];
+        let mut ranks = self.ranks.iter();
+
+        let mut stack: Vec<(&Phylo<T>, Option<usize>)> = vec![(&self.tree, None)];
+
+        while let Some((t, parent)) = stack.pop() {
+            match t {
+                Leaf(..)     => events[parent.unwrap()].1 += 1,
+                Node(ref ts) => {
+                    let r = *ranks.next().unwrap();
+                    if let Some(p) = parent {
+                        events[p].0.push(r);
+                    }
+
+                    stack.extend(ts.iter().rev().map(|ch| (ch, Some(r))));
+                }
+            }
+        }
+
+        events
+    }
+}
+
+
+impl<T: Clone> IndexedTree<T> {
+    // The tree ranked by the distances of its nodes from the root.
+    pub fn ranked(&self) -> RankedTree<T> {
+        RankedTree::from_indexed_times(self, &self.root_distances())
+    }
+}
+
+
+// Binomial coefficients exactly, or None when they do not fit. The partial products are
+// C(n, i) for i up to the smaller of k and n - k, and the division by i + 1 is cancelled
+// against C(n, i) first so that nothing larger than C(n, i + 1) is computed.
+fn binomial(n: u128, k: u128) -> Option<u128> {
+    (0..k.min(n - k)).try_fold(1u128, |c, i| {
+        let g = gcd(c, i + 1);
+        (c / g).checked_mul((n - i) / ((i + 1) / g))
+    })
+}
+
+impl<T> Phylo<T> {
+    // The number of orders in time of the internal nodes compatible with the tree, which is m!
+    // divided by the product of the numbers of internal nodes below every internal node.
+    pub fn count_rankings(&self) -> u128 {
+        let (rankings, _) = self.fold((1u128, 0u128), |_, it| {
+            it.fold((1u128, 1u128), |(c, m), (c1, m1)| {
+                let ways = binomial(m - 1 + m1, m1).and_then(|b| b.checked_mul(c1));
+                (ways.and_then(|w| w.checked_mul(c)).expect("too many rankings to count"), m + m1)
+            })
+        });
+
+        rankings
+    }
+}
@@ -0,0 +1,318 @@
+use std::collections::BTreeSet;
+use std::mem;
+
+use rand::Rng;
+
+use phylo::Phylo;
+
+
+// Sorts the children of every node, so that trees differing only in the order of children
+// become equal.
+fn canonical<T: Clone + Ord>(t: &Phylo<T>) -> Phylo<T> {
+    t.cata(|x| Phylo::leaf_with(x.clone()), |_, chs| {
+        let mut ts: Vec<Phylo<T>> = chs.collect();
+        ts.sort();
+        Phylo::node(ts)
+    })
+}
+
+fn paths<T>(t: &Phylo<T>) -> Vec<Vec<usize>> {
+    let mut paths = Vec::new();
+    t.for_each_with_path(|_, path| paths.push(path.to_vec()));
+    paths
+}
+
+// Exchanges the child `c` of the node at `path` with its sibling `s`.
+fn swap_with_sibling<T: Clone>(t: &Phylo<T>, path: &[usize], c: usize, s: usize) -> Phylo<T> {
+    let (&i, parent_path) = path.split_last().unwrap();
+    let parent = t.get_subtree(parent_path).unwrap();
+
+    let mut ts = parent.children().to_vec();
+    let mut us = ts[i].children().to_vec();
+
+    ts[s] = mem::replace(&mut us[c], ts[s].clone());
+    ts[i] = Phylo::node(us);
+
+    t.replace_at(parent_path, Phylo::node(ts)).unwrap()
+}
+
+// The same unrooted tree, rooted on the edge above the node at `path`. Unary nodes left at
+// the old root are suppressed.
+fn reroot<T: Clone>(t: &Phylo<T>, path: &[usize]) -> Phylo<T> {
+    if path.is_empty() {
+        return t.clone();
+    }
+
+    let without = |node: &Phylo<T>, i: usize| -> Vec<Phylo<T>> {
+        node.children().iter().enumerate().filter(|&(j, _)| j != i).map(|(_, ch)| ch.clone()).collect()
+    };
+
+    let mut node = t;
+    let mut up = {
+        let mut ts = without(node, path[0]);
+        if ts.len() == 1 { ts.pop().unwrap() } else { Phylo::node(ts) }
+    };
+
+    for k in 1..path.len() {
+        node = &node.children()[path[k-1]];
+
+        let mut ts = without(node, path[k]);
+        ts.push(up);
+        up = Phylo::node(ts);
+    }
+
+    let below = node.children()[path[path.len() - 1]].clone();
+    Phylo::node(vec![below, up])
+}
+
+fn non_root_paths<T>(t: &Phylo<T>) -> Vec<Vec<usize>> {
+    paths(t).into_iter().filter(|p| !p.is_empty()).collect()
+}
+
+
+impl<T: Clone + Ord> Phylo<T> {
+    // Rooted nearest neighbour interchanges: a child of a non-root internal node is exchanged
+    // with a sibling of that node. The resulting trees are given once each, with their
+    // children sorted, and exclude this tree.
+    pub fn nni_neighbours(&self) -> Vec<Self> {
+        let mut trees = BTreeSet::new();
+
+        for path in non_root_paths(self) {
+            let u = self.get_subtree(&path).unwrap();
+            let num_siblings = self.get_subtree(&path[..path.len() - 1]).unwrap().children().len();
+
+            for c in 0..u.children().len() {
+                for s in (0..num_siblings).filter(|&s| s != path[path.len() - 1]) {
+                    trees.insert(canonical(&swap_with_sibling(self, &path, c, s)));
+                }
+            }
+        }
+
+        trees.remove(&canonical(self));
+        trees.into_iter().collect()
+    }
+
+    // Subtree prune and regraft: a subtree is pruned and grafted on an edge of the rest of the
+    // tree, including a new edge above its root.
+    pub fn spr_neighbours(&self) -> Vec<Self> {
+        self.regraft_neighbours(false)
+    }
+
+    // Tree bisection and reconnection: like SPR, but the pruned subtree may be rerooted on any
+    // of its edges before grafting it.
+    pub fn tbr_neighbours(&self) -> Vec<Self> {
+        self.regraft_neighbours(true)
+    }
+
+    fn regraft_neighbours(&self, reroot_pruned: bool) -> Vec<Self> {
+        let mut trees = BTreeSet::new();
+
+        for path in non_root_paths(self) {
+            let (rest, pruned) = match self.prune(&path) {
+                Some(r) => r,
+                None    => continue
+            };
+
+            let pruned_roots = if reroot_pruned { paths(&pruned) } else { vec![Vec::new()] };
+
+            for root in pruned_roots {
+                let s = reroot(&pruned, &root);
+
+                for target in paths(&rest) {
+                    trees.insert(canonical(&rest.graft_on_edge(&target, s.clone()).unwrap()));
+                }
+            }
+        }
+
+        trees.remove(&canonical(self));
+        trees.into_iter().collect()
+    }
+
+    // A random NNI move, or None if the tree has no internal edges.
+    pub fn random_nni<R: Rng>(&self, rng: &mut R) -> Option<Self> {
+        let candidates: Vec<Vec<usize>> = non_root_paths(self).into_iter()
+            .filter(|p| {
+                !self.get_subtree(p).unwrap().is_leaf() &&
+                    self.get_subtree(&p[..p.len() - 1]).unwrap().children().len() > 1
+            })
+            .collect();
+
+        if candidates.is_empty() {
+            return None;
+        }
+
+        let path = &candidates[rng.random_range(0..candidates.len())];
+        let (&i, parent_path) = path.split_last().unwrap();
+
+        let c = rng.random_range(0..self.get_subtree(path).unwrap().children().len());
+        let mut s = rng.random_range(0..self.get_subtree(parent_path).unwrap().children().len() - 1);
+        if s >= i {
+            s += 1;
+        }
+
+        Some(swap_with_sibling(self, path, c, s))
+    }
+
+    // A random SPR move, uniform among those that change the tree, or None if there is none.
+    pub fn random_spr<R: Rng>(&self, rng: &mut R) -> Option<Self> {
+        self.random_regraft(rng, false)
+    }
+
+    pub fn random_tbr<R: Rng>(&self, rng: &mut R) -> Option<Self> {
+        self.random_regraft(rng, true)
+    }
+
+    // Every move that changes the tree, as the pruned subtree, its new root and the edge it is
+    // grafted on, is enumerated once so that one can be picked uniformly.
+    fn random_regraft<R: Rng>(&self, rng: &mut R, reroot_pruned: bool) -> Option<Self> {
+        let original = canonical(self);
+        let mut moves = Vec::new();
+
+        for path in non_root_paths(self) {
+            let (rest, pruned) = match self.prune(&path) {
+                Some(r) => r,
+                None    => continue
+            };
+
+            let pruned_roots = if reroot_pruned { paths(&pruned) } else { vec![Vec::new()] };
+
+            for root in pruned_roots {
+                let s = reroot(&pruned, &root);
+
+                for target in paths(&rest) {
+                    if canonical(&rest.graft_on_edge(&target, s.clone()).unwrap()) != original {
+                        moves.push((path.clone(), root.clone(), target));
+                    }
+                }
+            }
+        }
+
+        if moves.is_empty() {
+            return None;
+        }
+
+        let (path, root, target) = &moves[rng.random_range(0..moves.len())];
+        let (rest, pruned) = self.prune(path).unwrap();
+
+        rest.graft_on_edge(target, reroot(&pruned, root))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    use phylo::{LabelledTrees, Phylo};
+    use super::canonical;
+
+    fn set(ts: Vec<Phylo<u32>>) -> BTreeSet<Phylo<u32>> {
+        ts.into_iter().collect()
+    }
+
+    #[test]
+    fn nni_neighbourhoods() {
+        let labels: Vec<u32> = (0..6).collect();
+        let trees = LabelledTrees::binary(&labels);
+
+        // A rooted binary tree has n - 2 internal edges below the root, each giving two
+        // neighbours.
+        for i in 0..trees.num_trees() {
+            let t = trees.unrank(i);
+            let nni = t.nni_neighbours();
+
+            assert_eq!(nni.len(), 2 * (6 - 2));
+            assert!(set(nni).is_subset(&set(t.spr_neighbours())));
+        }
+
+        let leaf = |x| Phylo::leaf_with(x);
+        let star = Phylo::node(vec![leaf(0u32), leaf(1), leaf(2), leaf(3)]);
+        assert!(star.nni_neighbours().is_empty());
+
+        // Each leaf of (0, 1) can be exchanged with 2 or 3.
+        let t = Phylo::node(vec![Phylo::node(vec![leaf(0u32), leaf(1)]), leaf(2), leaf(3)]);
+        assert_eq!(t.nni_neighbours().len(), 4);
+        assert!(t.nni_neighbours().iter().all(|u| u.children().len() == 3));
+    }
+
+    #[test]
+    fn spr_neighbours_are_the_trees_at_rspr_distance_one() {
+        let labels: Vec<u32> = (0..5).collect();
+        let trees = LabelledTrees::binary(&labels);
+        let all: Vec<Phylo<u32>> = (0..trees.num_trees()).map(|i| canonical(&trees.unrank(i))).collect();
+
+        for t in &all {
+            let expected: BTreeSet<Phylo<u32>> = all.iter()
+                .filter(|u| t.rspr_distance(u) == 1)
+                .cloned()
+                .collect();
+
+            assert_eq!(set(t.spr_neighbours()), expected);
+        }
+    }
+
+    #[test]
+    fn tbr_neighbours_extend_spr_neighbours() {
+        let mut rng = StdRng::seed_from_u64(44);
+        let labels: Vec<u32> = (0..7).collect();
+        let trees = LabelledTrees::binary(&labels);
+
+        for _ in 0..10 {
+            let t = trees.unrank(rng.random_range(0..trees.num_trees()));
+            let tbr = t.tbr_neighbours();
+
+            assert!(set(t.spr_neighbours()).is_subset(&set(tbr.clone())));
+            assert!(tbr.iter().all(|u| t.tbr_distance(u) <= 1));
+        }
+    }
+
+    #[test]
+    fn random_nnis_are_neighbours() {
+        let mut rng = StdRng::seed_from_u64(44);
+        let leaf = |x| Phylo::leaf_with(x);
+        let t = Phylo::node(vec![
+            Phylo::node(vec![leaf(0u32), leaf(1), leaf(2)]),
+            Phylo::node(vec![leaf(3), Phylo::node(vec![leaf(4), leaf(5)])])
+        ]);
+        let nni = set(t.nni_neighbours());
+
+        for _ in 0..50 {
+            assert!(nni.contains(&canonical(&t.random_nni(&mut rng).unwrap())));
+        }
+
+        assert_eq!(Phylo::node(vec![leaf(0u32), leaf(1), leaf(2)]).random_nni(&mut rng), None);
+        assert_eq!(leaf(0u32).random_nni(&mut rng), None);
+    }
+
+    #[test]
+    fn random_regrafts_are_changing_neighbours() {
+        let mut rng = StdRng::seed_from_u64(44);
+        let t = Phylo::node(vec![
+            Phylo::node(vec![Phylo::leaf_with(0u32), Phylo::leaf_with(1)]),
+            Phylo::node(vec![Phylo::leaf_with(2), Phylo::node(vec![Phylo::leaf_with(3), Phylo::leaf_with(4)])])
+        ]);
+
+        let spr: Vec<Phylo<u32>> = t.spr_neighbours().iter().map(canonical).collect();
+        let tbr: Vec<Phylo<u32>> = t.tbr_neighbours().iter().map(canonical).collect();
+
+        for _ in 0..50 {
+            assert!(spr.contains(&canonical(&t.random_spr(&mut rng).unwrap())));
+            assert!(tbr.contains(&canonical(&t.random_tbr(&mut rng).unwrap())));
+        }
+    }
+
+    #[test]
+    fn trees_with_no_changing_moves_have_no_random_regraft() {
+        let mut rng = StdRng::seed_from_u64(44);
+        let cherry = Phylo::node(vec![Phylo::leaf_with(0u32), Phylo::leaf_with(1)]);
+        let star = Phylo::node(vec![Phylo::leaf_with(0u32), Phylo::leaf_with(1), Phylo::leaf_with(2)]);
+
+        assert_eq!(cherry.random_spr(&mut rng), None);
+        assert_eq!(cherry.random_tbr(&mut rng), None);
+        assert_eq!(Phylo::leaf_with(0u32).random_spr(&mut rng), None);
+        assert!(star.random_spr(&mut rng).is_some());
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::HashSet;
+use std::hash::Hash;
+
+use phylo::{Phylo, Leaf};
+use phylo::arena::IndexedTree;
+
+
+impl<T: Clone + Hash + Eq> Phylo<T> {
+    // The subtree induced by the leaves in `keep`, without the unary nodes left behind.
+    // Subtrees that keep all their leaves are shared with the original tree.
+    pub fn restrict(&self, keep: &[T]) -> Self {
+        let keep: HashSet<&T> = keep.iter().collect();
+
+        let (t, _) = self.cata(
+            |x| (if keep.contains(x) { Some(Leaf(x.clone())) } else { None }, keep.contains(x)),
+            |t, rs| {
+                let mut complete = true;
+                let mut ts = Vec::new();
+
+                for (r, c) in rs {
+                    complete &= c;
+                    ts.extend(r);
+                }
+
+                if complete {
+                    (Some(t.clone()), true)
+                } else if ts.len() > 1 {
+                    (Some(Phylo::node(ts)), false)
+                } else {
+                    (ts.pop(), false)
+                }
+            });
+
+        t.expect("no leaves kept")
+    }
+}
+
+impl<T: Clone + Hash + Eq> IndexedTree<T> {
+    // The subtree induced by the leaves in `keep`, rooted at their lowest common ancestor.
+    // The edges around a suppressed unary node are merged, adding up their known lengths.
+    pub fn restrict(&self, keep: &[T]) -> Self {
+        let keep: HashSet<&T> = keep.iter().collect();
+        let n = self.len();
+
+        let mut kept_leaves = vec![0; n];
+        let mut kept_children = vec![0; n];
+
+        for id in self.post_order() {
+            let i = id.index();
+
+            if self.is_leaf(id) && self.label(id).is_some_and(|x| keep.contains(x)) {
+                kept_leaves[i] = 1;
+            }
+
+            if let Some(p) = self.parent(id) {
+                kept_leaves[p.index()] += kept_leaves[i];
+
+                if kept_leaves[i] > 0 {
+                    kept_children[p.index()] += 1;
+                }
+            }
+        }
+
+        assert!(kept_leaves[self.root().index()] > 0, "no leaves kept");
+
+        let mut restricted = IndexedTree::new();
+        let mut ids = vec![None; n];
+        let mut lengths: Vec<Option<f64>> = vec![None; n];
+        let mut anchors = vec![None; n];
+
+        for id in self.pre_order() {
+            let i = id.index();
+
+            if kept_leaves[i] == 0 {
+                continue;
+            }
+
+            // The nearest kept ancestor, and the length of the path to it.
+            if let Some(p) = self.parent(id) {
+                let (anchor, above) = match ids[p.index()] {
+                    Some(q) => (Some(q), None),
+                    None    => (anchors[p.index()], lengths[p.index()])
+                };
+
+                anchors[i] = anchor;
+                lengths[i] = match (above, self.length(id)) {
+                    (Some(a), Some(l)) => Some(a + l),
+                    (a, l)             => a.or(l)
+                };
+            }
+
+            if self.is_leaf(id) || kept_children[i] > 1 {
+                let new_id = restricted.add_node(anchors[i], self.label(id).cloned());
+
+                if anchors[i].is_some() {
+                    restricted.set_length(new_id, lengths[i]);
+                }
+
+                ids[i] = Some(new_id);
+            }
+        }
+
+        restricted
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+
+    use phylo::{LabelledTrees, NodeId, Phylo};
+
+    fn leaf(x: char) -> Phylo<char> {
+        Phylo::leaf_with(x)
+    }
+
+    // ((a, b), (c, (d, e)))
+    fn example() -> Phylo<char> {
+        Phylo::node(vec![
+            Phylo::node(vec![leaf('a'), leaf('b')]),
+            Phylo::node(vec![leaf('c'), Phylo::node(vec![leaf('d'), leaf('e')])])
+        ])
+    }
+
+    fn clusters(t: &Phylo<u32>) -> BTreeSet<BTreeSet<u32>> {
+        t.pre_order().map(|v| v.node.get_leaves().into_iter().cloned().collect()).collect()
+    }
+
+    #[test]
+    fn restrictions_of_small_trees() {
+        let t = example();
+
+        assert_eq!(t.restrict(&['a', 'd', 'e']), Phylo::node(vec![
+            leaf('a'),
+            Phylo::node(vec![leaf('d'), leaf('e')])
+        ]));
+        assert_eq!(t.restrict(&['c', 'd', 'e']), t.children()[1]);
+        assert_eq!(t.restrict(&['e', 'x']), leaf('e'));
+        assert_eq!(t.restrict(&['a', 'b', 'c', 'd', 'e']), t);
+        assert_eq!(leaf('a').restrict(&['a']), leaf('a'));
+
+        assert_eq!(t.to_indexed().restrict(&['a', 'd', 'e']).to_phylo(), t.restrict(&['a', 'd', 'e']));
+        assert_eq!(t.to_indexed().restrict(&['e']).to_phylo(), leaf('e'));
+    }
+
+    #[test]
+    fn complete_subtrees_are_shared() {
+        let t = example();
+        let r = t.restrict(&['a', 'b', 'd']);
+
+        assert_eq!(r, Phylo::node(vec![Phylo::node(vec![leaf('a'), leaf('b')]), leaf('d')]));
+        assert!(::std::ptr::eq(r.children()[0].children().as_ptr(), t.children()[0].children().as_ptr()));
+    }
+
+    #[test]
+    #[should_panic(expected = "no leaves kept")]
+    fn empty_restrictions_panic() {
+        example().restrict(&[]);
+    }
+
+    #[test]
+    #[should_panic(expected = "no leaves kept")]
+    fn empty_indexed_restrictions_panic() {
+        example().to_indexed().restrict(&['x']);
+    }
+
+    #[test]
+    fn restrictions_keep_the_induced_clusters() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let labels: Vec<u32> = (0..9).collect();
+        let trees = LabelledTrees::all(&labels);
+
+        for _ in 0..50 {
+            let t = trees.unrank(rng.random_range(0..trees.num_trees()));
+
+            let mut keep = labels.clone();
+            keep.shuffle(&mut rng);
+            keep.truncate(rng.random_range(1..10));
+
+            let expected: BTreeSet<BTreeSet<u32>> = clusters(&t).into_iter()
+                .map(|c| c.into_iter().filter(|x| keep.contains(x)).collect::<BTreeSet<u32>>())
+                .filter(|c| !c.is_empty())
+                .collect();
+
+            let r = t.restrict(&keep);
+            assert_eq!(clusters(&r), expected);
+            assert!(r.pre_order().all(|v| v.node.is_leaf() || v.node.children().len() > 1));
+            assert_eq!(t.to_indexed().restrict(&keep).to_phylo(), r);
+        }
+    }
+
+    #[test]
+    fn merged_edges_add_up_their_lengths() {
+        // ((a:1, b:2):3, c:4), restricted to a and c
+        let mut t = Phylo::node(vec![Phylo::node(vec![leaf('a'), leaf('b')]), leaf('c')]).to_indexed();
+        for &(i, l) in &[(1, 3.0), (2, 1.0), (3, 2.0), (4, 4.0)] {
+            t.set_length(NodeId::from_index(i), Some(l));
+        }
+
+        let r = t.restrict(&['a', 'c']);
+        let (a, c) = (r.find_leaf(&'a').unwrap(), r.find_leaf(&'c').unwrap());
+
+        assert_eq!(r.len(), 3);
+        assert_eq!(r.length(a), Some(4.0));
+        assert_eq!(r.length(c), Some(4.0));
+        assert_eq!(r.length(r.root()), None);
+
+        // Only the known lengths of the merged edges are added up.
+        t.set_length(NodeId::from_index(1), None);
+        let r = t.restrict(&['a', 'c']);
+        assert_eq!(r.length(r.find_leaf(&'a').unwrap()), Some(1.0));
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use phylo::Phylo;
+use phylo::arena::{IndexedTree, NodeId};
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RobinsonFoulds {
+    pub only_first: u32,
+    pub only_second: u32,
+    pub shared: u32,
+    pub weighted: f64
+}
+
+impl RobinsonFoulds {
+    pub fn distance(&self) -> u32 {
+        self.only_first + self.only_second
+    }
+
+    // The distance divided by the total number of non-trivial clusters or splits in both trees.
+    pub fn normalized(&self) -> f64 {
+        let total = self.only_first + self.only_second + 2*self.shared;
+
+        if total == 0 {
+            0.0
+        } else {
+            self.distance() as f64 / total as f64
+        }
+    }
+}
+
+
+// Clusters are keyed by the (min, max, size) of the ranks of their leaves. Ranks are given by
+// a post-order of the first tree, so that its clusters are intervals and two trees share a
+// cluster iff they share its key (Day, 1985). Every edge adds its length to the cluster it
+// induces, so that the edges around a suppressed unary node count as a single one.
+fn cluster_table<'t, T>(tree: &'t IndexedTree<T>,
+                        start: NodeId,
+                        ranks: &mut HashMap<&'t T, u32>,
+                        assign_ranks: bool) -> HashMap<(u32, u32, u32), f64>
+    where T: Hash + Eq {
+
+    let n = tree.len();
+    let mut min = vec![u32::MAX; n];
+    let mut max = vec![0; n];
+    let mut size = vec![0; n];
+
+    let mut table = HashMap::new();
+
+    for (id, parent) in tree.post_order_from(start) {
+        let parent = match parent {
+            Some(p) => p,
+            None    => continue
+        };
+
+        let i = id.index();
+
+        if size[i] == 0 {
+            let x = tree.label(id).expect("unlabelled leaf");
+
+            let rank = if assign_ranks {
+                let next = ranks.len() as u32;
+                *ranks.entry(x).or_insert(next)
+            } else {
+                *ranks.get(x).expect("trees have different leaf sets")
+            };
+
+            min[i] = rank;
+            max[i] = rank;
+            size[i] = 1;
+        }
+
+        let key = (min[i], max[i], size[i]);
+        *table.entry(key).or_insert(0.0) += tree.edge_length(id, parent).unwrap_or(1.0);
+
+        let p = parent.index();
+        min[p] = min[p].min(min[i]);
+        max[p] = max[p].max(max[i]);
+        size[p] += size[i];
+    }
+
+    table
+}
+
+fn compare_cluster_tables<F>(t1: &HashMap<(u32, u32, u32), f64>,
+                             t2: &HashMap<(u32, u32, u32), f64>,
+                             nontrivial: F) -> RobinsonFoulds
+    where F: Fn(u32) -> bool {
+
+    let mut rf = RobinsonFoulds { only_first: 0, only_second: 0, shared: 0, weighted: 0.0 };
+
+    for (key, w1) in t1 {
+        let w2 = t2.get(key);
+
+        if nontrivial(key.2) {
+            match w2 {
+                Some(..) => rf.shared += 1,
+                None     => rf.only_first += 1
+            }
+        }
+
+        rf.weighted += (w1 - w2.cloned().unwrap_or(0.0)).abs();
+    }
+
+    for (key, w2) in t2 {
+        if !t1.contains_key(key) {
+            if nontrivial(key.2) {
+                rf.only_second += 1;
+            }
+
+            rf.weighted += w2.abs();
+        }
+    }
+
+    rf
+}
+
+
+impl<T: Hash + Eq> IndexedTree<T> {
+    fn num_leaves(&self) -> u32 {
+        self.leaves().count() as u32
+    }
+
+    // Compares the clusters of both trees. Weights add up the differences between the lengths
+    // of the edges inducing each cluster, including pendant edges; missing lengths count as one.
+    pub fn rooted_robinson_foulds(&self, other: &Self) -> RobinsonFoulds {
+        let n = self.num_leaves();
+        assert_eq!(n, other.num_leaves(), "trees have different leaf sets");
+
+        let mut ranks = HashMap::new();
+        let t1 = cluster_table(self, self.root(), &mut ranks, true);
+        let t2 = cluster_table(other, other.root(), &mut ranks, false);
+
+        compare_cluster_tables(&t1, &t2, |size| size > 1 && size < n)
+    }
+
+    // Compares the splits of the unrooted versions of both trees.
+    pub fn unrooted_robinson_foulds(&self, other: &Self) -> RobinsonFoulds {
+        let n = self.num_leaves();
+        assert_eq!(n, other.num_leaves(), "trees have different leaf sets");
+
+        let r1 = self.leaves().next().unwrap();
+        let x = self.label(r1).expect("unlabelled leaf");
+        let r2 = other.find_leaf(x).expect("trees have different leaf sets");
+
+        let mut ranks = HashMap::new();
+        let t1 = cluster_table(self, r1, &mut ranks, true);
+        let t2 = cluster_table(other, r2, &mut ranks, false);
+
+        compare_cluster_tables(&t1, &t2, |size| size > 1 && size + 1 < n)
+    }
+}
+
+impl<T: Clone + Hash + Eq> Phylo<T> {
+    pub fn rooted_rf_distance(&self, other: &Self) -> u32 {
+        self.to_indexed().rooted_robinson_foulds(&other.to_indexed()).distance()
+    }
+
+    pub fn unrooted_rf_distance(&self, other: &Self) -> u32 {
+        self.to_indexed().unrooted_robinson_foulds(&other.to_indexed()).distance()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    use phylo::{LabelledTrees, NodeId, Phylo};
+
+    fn leaf(x: char) -> Phylo<char> {
+        Phylo::leaf_with(x)
+    }
+
+    fn clusters(t: &Phylo<u32>) -> Vec<BTreeSet<u32>> {
+        t.pre_order()
+            .map(|v| v.node.get_leaves().into_iter().cloned().collect())
+            .collect()
+    }
+
+    fn brute_force_rooted(t1: &Phylo<u32>, t2: &Phylo<u32>, n: usize) -> u32 {
+        let nontrivial = |t| -> BTreeSet<BTreeSet<u32>> {
+            clusters(t).into_iter().filter(|c| c.len() > 1 && c.len() < n).collect()
+        };
+
+        nontrivial(t1).symmetric_difference(&nontrivial(t2)).count() as u32
+    }
+
+    // Splits are represented by their side without the leaf 0.
+    fn brute_force_unrooted(t1: &Phylo<u32>, t2: &Phylo<u32>, n: usize) -> u32 {
+        let splits = |t| -> BTreeSet<BTreeSet<u32>> {
+            clusters(t).into_iter()
+                .map(|c| if c.contains(&0) { (0..n as u32).filter(|x| !c.contains(x)).collect() } else { c })
+                .filter(|c: &BTreeSet<u32>| c.len() > 1 && c.len() + 1 < n)
+                .collect()
+        };
+
+        splits(t1).symmetric_difference(&splits(t2)).count() as u32
+    }
+
+    #[test]
+    fn distances_match_brute_force() {
+        let mut rng = StdRng::seed_from_u64(35);
+
+        for n in 1..9 {
+            let labels: Vec<u32> = (0..n).collect();
+            let trees = LabelledTrees::all(&labels);
+
+            for _ in 0..20 {
+                let t1 = trees.unrank(rng.random_range(0..trees.num_trees()));
+                let t2 = trees.unrank(rng.random_range(0..trees.num_trees()));
+
+                assert_eq!(t1.rooted_rf_distance(&t2), brute_force_rooted(&t1, &t2, n as usize));
+                assert_eq!(t1.unrooted_rf_distance(&t2), brute_force_unrooted(&t1, &t2, n as usize));
+                assert_eq!(t1.rooted_rf_distance(&t1), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn rerooted_trees_have_no_unrooted_distance() {
+        // ((a, b), (c, d), e) and (a, b, ((c, d), e))
+        let t1 = Phylo::node(vec![
+            Phylo::node(vec![leaf('a'), leaf('b')]),
+            Phylo::node(vec![leaf('c'), leaf('d')]),
+            leaf('e')
+        ]);
+        let t2 = Phylo::node(vec![
+            leaf('a'),
+            leaf('b'),
+            Phylo::node(vec![Phylo::node(vec![leaf('c'), leaf('d')]), leaf('e')])
+        ]);
+
+        assert_eq!(t1.unrooted_rf_distance(&t2), 0);
+        assert_eq!(t1.rooted_rf_distance(&t2), 2);
+    }
+
+    #[test]
+    fn normalised_distances_of_multifurcating_trees() {
+        // ((a, b), (c, d), e) and ((a, b), c, d, e)
+        let t1 = Phylo::node(vec![
+            Phylo::node(vec![leaf('a'), leaf('b')]),
+            Phylo::node(vec![leaf('c'), leaf('d')]),
+            leaf('e')
+        ]).to_indexed();
+        let t2 = Phylo::node(vec![
+            Phylo::node(vec![leaf('a'), leaf('b')]),
+            leaf('c'),
+            leaf('d'),
+            leaf('e')
+        ]).to_indexed();
+
+        let rf = t1.rooted_robinson_foulds(&t2);
+        assert_eq!((rf.only_first, rf.only_second, rf.shared), (1, 0, 1));
+        assert_eq!(rf.distance(), 1);
+        assert_eq!(rf.normalized(), 1.0 / 3.0);
+
+        let star = Phylo::node(vec![leaf('a'), leaf('b'), leaf('c')]).to_indexed();
+        assert_eq!(star.unrooted_robinson_foulds(&star).normalized(), 0.0);
+    }
+
+    #[test]
+    fn weighted_distances() {
+        // ((a, b):2, c) and ((a, c), b:3), with every other edge of length one
+        let mut t1 = Phylo::node(vec![Phylo::node(vec![leaf('a'), leaf('b')]), leaf('c')]).to_indexed();
+        let mut t2 = Phylo::node(vec![Phylo::node(vec![leaf('a'), leaf('c')]), leaf('b')]).to_indexed();
+        t1.set_length(NodeId::from_index(1), Some(2.0));
+        t2.set_length(NodeId::from_index(4), Some(3.0));
+
+        let rf = t1.rooted_robinson_foulds(&t2);
+        assert_eq!(rf.distance(), 2);
+        assert_eq!(rf.normalized(), 1.0);
+        assert_eq!(rf.weighted, 5.0);
+
+        assert_eq!(t1.rooted_robinson_foulds(&t1).weighted, 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "trees have different leaf sets")]
+    fn different_leaf_sets_panic() {
+        let t1 = Phylo::node(vec![leaf('a'), leaf('b')]);
+        let t2 = Phylo::node(vec![leaf('a'), leaf('c')]);
+
+        t1.rooted_rf_distance(&t2);
+    }
+}
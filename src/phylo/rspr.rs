@@ -0,0 +1,980 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use phylo::Phylo;
+use phylo::arena::{IndexedTree, NodeId};
+use phylo::lca::Lca;
+
+
+// A forest of rooted binary trees whose leaves carry labels. Labels are created when a
+// cherry common to both trees is contracted into a single leaf. Children are kept inline so
+// that the search copies forests cheaply.
+#[derive(Clone)]
+struct Forest {
+    parent: Vec<Option<usize>>,
+    children: Vec<Option<[usize; 2]>>,
+    alive: Vec<bool>,
+    label: Vec<Option<usize>>,
+    node_of: Vec<usize>
+}
+
+impl Forest {
+    fn add(&mut self, parent: Option<usize>, label: Option<usize>) -> usize {
+        let v = self.parent.len();
+
+        self.parent.push(parent);
+        self.alive.push(true);
+        self.label.push(label);
+
+        if let Some(l) = label {
+            if self.node_of.len() <= l {
+                self.node_of.resize(l + 1, 0);
+            }
+            self.node_of[l] = v;
+        }
+
+        v
+    }
+
+    // A rooted tree gets an extra leaf labelled `rho` as a sibling of its root, which makes
+    // the root behave like any other node. An unrooted one may have three children at its
+    // root, of which the last two are joined below an extra node.
+    fn new<T: Hash + Eq>(tree: &IndexedTree<T>, labels: &HashMap<&T, usize>, rho: Option<usize>) -> Self {
+        let mut forest = Forest {
+            parent: Vec::new(),
+            children: Vec::new(),
+            alive: Vec::new(),
+            label: Vec::new(),
+            node_of: Vec::new()
+        };
+
+        let top = rho.map(|rho| {
+            let top = forest.add(None, None);
+            forest.add(Some(top), Some(rho));
+            top
+        });
+
+        let root = tree.root();
+        let trifurcation = rho.is_none() && tree.num_children(root) == 3;
+
+        let mut ids = vec![0; tree.len()];
+        let mut joined = None;
+
+        for id in tree.node_ids() {
+            let mut parent = tree.parent(id).map(|p| ids[p.index()]).or(top);
+
+            if trifurcation && tree.parent(id) == Some(root) && tree.first_child(root) != Some(id) {
+                parent = Some(*joined.get_or_insert_with(|| forest.add(parent, None)));
+            }
+
+            let label = match tree.num_children(id) {
+                0 => {
+                    let x = tree.label(id).expect("unlabelled leaf");
+                    Some(*labels.get(x).expect("trees have different leaf sets"))
+                },
+                2 => None,
+                3 if id == root && trifurcation => None,
+                _ => panic!("agreement forests need binary trees")
+            };
+
+            ids[id.index()] = forest.add(parent, label);
+        }
+
+        let mut children = vec![Vec::new(); forest.parent.len()];
+        for (v, &p) in forest.parent.iter().enumerate() {
+            if let Some(p) = p {
+                children[p].push(v);
+            }
+        }
+
+        forest.children = children.into_iter().map(|chs| match chs[..] {
+            [a, c] => Some([a, c]),
+            _      => None
+        }).collect();
+
+        forest
+    }
+
+    fn children(&self, v: usize) -> &[usize] {
+        self.children[v].as_ref().map_or(&[], |chs| &chs[..])
+    }
+
+    fn is_leaf(&self, v: usize) -> bool {
+        self.children[v].is_none()
+    }
+
+    fn root_of(&self, mut v: usize) -> usize {
+        while let Some(p) = self.parent[v] {
+            v = p;
+        }
+
+        v
+    }
+
+    fn sibling(&self, v: usize) -> Option<usize> {
+        let p = self.parent[v]?;
+        self.children(p).iter().cloned().find(|&ch| ch != v)
+    }
+
+    // Cuts the edge above `v`, suppressing its parent. Returns the sibling of `v` that takes
+    // the place of the parent, or None if `v` is a root.
+    fn cut(&mut self, v: usize) -> Option<usize> {
+        let p = self.parent[v]?;
+
+        let ch = self.sibling(v).unwrap();
+        let g = self.parent[p];
+
+        self.parent[v] = None;
+        self.children[p] = None;
+
+        self.parent[ch] = g;
+        if let Some(g) = g {
+            for x in self.children[g].iter_mut().flatten().filter(|x| **x == p) {
+                *x = ch;
+            }
+        }
+
+        self.alive[p] = false;
+        Some(ch)
+    }
+
+    // Removes the leaf `v` and suppresses its parent. Returns the node whose children changed,
+    // if any.
+    fn remove(&mut self, v: usize) -> Option<usize> {
+        let ch = self.cut(v);
+        self.alive[v] = false;
+        self.parent[ch?]
+    }
+
+    fn contract(&mut self, p: usize, label: usize) {
+        for ch in self.children[p].take().into_iter().flatten() {
+            self.alive[ch] = false;
+        }
+
+        self.label[p] = Some(label);
+
+        if self.node_of.len() <= label {
+            self.node_of.resize(label + 1, 0);
+        }
+        self.node_of[label] = p;
+    }
+
+    fn is_cherry(&self, v: usize) -> bool {
+        self.alive[v] && !self.is_leaf(v) && self.children(v).iter().all(|&ch| self.is_leaf(ch))
+    }
+
+    fn depth(&self, mut v: usize) -> usize {
+        let mut d = 0;
+
+        while let Some(p) = self.parent[v] {
+            v = p;
+            d += 1;
+        }
+
+        d
+    }
+
+    // Siblings of the nodes on the path from `a` and `c` up to (but excluding) their lowest
+    // common ancestor, which must exist, and that ancestor.
+    fn pendants(&self, a: usize, c: usize) -> (Vec<usize>, usize) {
+        let (mut a, mut c) = (a, c);
+        let (mut da, mut dc) = (self.depth(a), self.depth(c));
+        let mut pendants = Vec::new();
+
+        while a != c {
+            if da >= dc {
+                pendants.push(self.sibling(a).unwrap());
+                a = self.parent[a].unwrap();
+                da -= 1;
+            } else {
+                pendants.push(self.sibling(c).unwrap());
+                c = self.parent[c].unwrap();
+                dc -= 1;
+            }
+        }
+
+        // The last pendant found on each side is the other side of the path itself.
+        let [pa, pc] = self.children[a].unwrap();
+        pendants.retain(|&b| b != pa && b != pc);
+        (pendants, a)
+    }
+
+    // The subtrees pendant from the path between `a` and `c` when components are unrooted:
+    // the rest of the component above their lowest common ancestor is one more, whose edge is
+    // the one above that ancestor.
+    fn unrooted_pendants(&self, a: usize, c: usize) -> Vec<usize> {
+        let (mut pendants, lca) = self.pendants(a, c);
+
+        if self.parent[lca].is_some() {
+            pendants.push(lca);
+        }
+
+        pendants
+    }
+
+    // Makes siblings of two leaves joined through the root of their component, with one
+    // subtree pendant from the path between them, without changing the unrooted component.
+    fn join_at_root(&mut self, a: usize, c: usize) {
+        let (near, far) = if self.parent[self.parent[a].unwrap()].is_none() { (a, c) } else { (c, a) };
+
+        let w = self.parent[far].unwrap();
+        let r = self.parent[w].unwrap();
+        let b = self.sibling(far).unwrap();
+
+        self.children[r] = Some([w, b]);
+        self.parent[b] = Some(r);
+
+        self.children[w] = Some([far, near]);
+        self.parent[near] = Some(w);
+    }
+}
+
+
+enum Step {
+    Done,
+    Contracted,
+    Branch(Vec<Vec<usize>>)
+}
+
+// The state of the search for an agreement forest: the first tree, reduced as its cherries
+// are found in the forest, and the forest obtained by cutting edges of the second tree.
+// Components of the forest are unrooted for TBR distances. The nodes of the first tree that
+// may have become cherries and the leaves of the forest that may have become isolated are kept
+// aside so that each step only looks at them.
+#[derive(Clone)]
+struct MafState {
+    t1: Forest,
+    f2: Forest,
+    unrooted: bool,
+    leaves: usize,
+    next_label: usize,
+    merged: Vec<(usize, usize)>,
+    cuts: u32,
+    cherries: Vec<usize>,
+    isolated: Vec<usize>
+}
+
+impl MafState {
+    // Labels 0 to n - 1 are the leaves of the trees and n is the one standing for the root.
+    fn new(t1: Forest, f2: Forest, unrooted: bool, n: usize) -> Self {
+        let cherries = (0..t1.children.len()).rev().filter(|&v| t1.is_cherry(v)).collect();
+        let isolated = (0..f2.children.len()).filter(|&v| f2.alive[v] && f2.is_leaf(v)).collect();
+
+        MafState {
+            t1,
+            f2,
+            unrooted,
+            leaves: if unrooted { n } else { n + 1 },
+            next_label: n + 1,
+            merged: Vec::new(),
+            cuts: 0,
+            cherries,
+            isolated
+        }
+    }
+
+    fn contract(&mut self, a1: usize, a: usize) {
+        let label = self.next_label;
+        self.next_label += 1;
+
+        let p1 = self.t1.parent[a1].unwrap();
+        let p2 = self.f2.parent[a].unwrap();
+        let [x, y] = self.t1.children[p1].unwrap();
+        self.merged.push((self.t1.label[x].unwrap(), self.t1.label[y].unwrap()));
+
+        self.t1.contract(p1, label);
+        self.f2.contract(p2, label);
+
+        self.cherries.extend(self.t1.parent[p1]);
+        if self.f2.parent[p2].is_none() {
+            self.isolated.push(p2);
+        }
+    }
+
+    fn cherry(&mut self) -> Option<(usize, usize)> {
+        while let Some(&v) = self.cherries.last() {
+            if self.t1.is_cherry(v) {
+                let [a, c] = self.t1.children[v].unwrap();
+                return Some((a, c));
+            }
+
+            self.cherries.pop();
+        }
+
+        None
+    }
+
+    // Removes from the first tree the leaves that are already isolated in the forest,
+    // contracts common cherries, and returns the alternative sets of edges of the forest to
+    // cut when a cherry of the first tree is not one of the forest.
+    fn step(&mut self, approximate: bool) -> Step {
+        while let Some(w) = self.isolated.pop() {
+            if !(self.f2.alive[w] && self.f2.parent[w].is_none() && self.f2.is_leaf(w)) {
+                continue;
+            }
+
+            let v = self.t1.node_of[self.f2.label[w].unwrap()];
+            if self.t1.alive[v] && self.t1.parent[v].is_some() {
+                self.cherries.extend(self.t1.remove(v));
+            }
+        }
+
+        let (a1, c1) = match self.cherry() {
+            Some(pair) => pair,
+            None       => return Step::Done
+        };
+
+        let (a, c) = (self.f2.node_of[self.t1.label[a1].unwrap()], self.f2.node_of[self.t1.label[c1].unwrap()]);
+
+        // Some maximum agreement forest cuts the edge above a or the one above c.
+        if self.f2.root_of(a) != self.f2.root_of(c) {
+            return if approximate {
+                Step::Branch(vec![vec![a, c]])
+            } else {
+                Step::Branch(vec![vec![a], vec![c]])
+            };
+        }
+
+        let pendants = if self.unrooted { self.f2.unrooted_pendants(a, c) } else { self.f2.pendants(a, c).0 };
+
+        if pendants.is_empty() || (self.unrooted && pendants.len() == 1) {
+            if self.f2.parent[a] != self.f2.parent[c] {
+                self.f2.join_at_root(a, c);
+            }
+
+            self.contract(a1, a);
+            return Step::Contracted;
+        }
+
+        let branches = match (self.unrooted, approximate) {
+            // Bordewich, McCartin and Semple (2008): cutting the edges above a, c and the
+            // sibling of the deeper of them removes at least one edge of some maximum
+            // agreement forest.
+            (false, true) => {
+                let deeper = if self.f2.depth(a) >= self.f2.depth(c) { a } else { c };
+                vec![vec![a, c, self.f2.sibling(deeper).unwrap()]]
+            },
+
+            // Whidden, Beiko and Zeh (2013): some maximum agreement forest cuts the edge above
+            // a, the one above c, or those of all the subtrees pendant from the path between,
+            // and it cuts the edge of a single pendant subtree. Since the last option cuts two
+            // edges or more, the search takes O(2.42^k) steps.
+            (false, false) => {
+                if pendants.len() == 1 {
+                    vec![pendants]
+                } else {
+                    vec![vec![a], vec![c], pendants]
+                }
+            },
+
+            // Unrooted components only need a and c to be a cherry, keeping one of the
+            // pendant subtrees. Some maximum agreement forest cuts one of the edges above the
+            // first and last pendant subtrees, a and c, which are cut in this order since
+            // cutting a or c may suppress their lowest common ancestor.
+            (true, true) => vec![vec![pendants[0], pendants[pendants.len() - 1], a, c]],
+
+            (true, false) => {
+                let mut branches = vec![vec![a], vec![c]];
+
+                for i in 0..pendants.len() {
+                    let mut edges = pendants.clone();
+                    edges.remove(i);
+                    branches.push(edges);
+                }
+
+                branches
+            }
+        };
+
+        Step::Branch(branches)
+    }
+
+    // The components of the forest as a partition of the original leaves, each component
+    // labelled by its first leaf. It determines the rest of the state.
+    fn partition(&self) -> Vec<u32> {
+        let mut first = vec![None; self.f2.parent.len()];
+
+        (0..self.leaves)
+            .map(|l| *first[self.f2.root_of(self.f2.node_of[l])].get_or_insert(l as u32))
+            .collect()
+    }
+
+    fn cut_all(&mut self, edges: &[usize]) {
+        for &v in edges {
+            if let Some(ch) = self.f2.cut(v) {
+                self.cuts += 1;
+                self.isolated.push(v);
+                if self.f2.parent[ch].is_none() {
+                    self.isolated.push(ch);
+                }
+            }
+        }
+    }
+
+    // Depth-first branch and bound for a forest with fewer than `bound` cuts. The approximation
+    // completes every state that branches, improving the best forest found so far, and bounds
+    // the cuts any forest reached from it needs.
+    fn search(mut self, bound: &mut u32, best: &mut Option<MafState>, seen: &mut HashMap<Vec<u32>, u32>) {
+        loop {
+            match self.step(false) {
+                Step::Done => {
+                    if self.cuts < *bound {
+                        *bound = self.cuts;
+                        *best = Some(self);
+                    }
+                    return;
+                },
+
+                Step::Contracted => continue,
+
+                Step::Branch(options) => {
+                    let key = self.partition();
+                    if let Some(&rest) = seen.get(&key) {
+                        if self.cuts + rest >= *bound {
+                            return;
+                        }
+                    }
+
+                    let ratio = if self.unrooted { 4 } else { 3 };
+                    let approx = self.clone().approximate();
+                    let lower = self.cuts + (approx.cuts - self.cuts).div_ceil(ratio);
+
+                    if approx.cuts < *bound {
+                        *bound = approx.cuts;
+                        *best = Some(approx);
+                    }
+
+                    for edges in options {
+                        if lower < *bound && self.cuts + (edges.len() as u32) < *bound {
+                            let mut next = self.clone();
+                            next.cut_all(&edges);
+                            next.search(bound, best, seen);
+                        }
+                    }
+
+                    let rest = *bound - self.cuts;
+                    let entry = seen.entry(key).or_insert(rest);
+                    *entry = (*entry).max(rest);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn best_below(state: MafState, mut bound: u32) -> Option<MafState> {
+        let mut best = None;
+        state.search(&mut bound, &mut best, &mut HashMap::new());
+        best
+    }
+
+    fn approximate(mut self) -> Self {
+        loop {
+            match self.step(true) {
+                Step::Done              => return self,
+                Step::Contracted        => continue,
+                Step::Branch(mut edges) => self.cut_all(&edges.pop().unwrap())
+            }
+        }
+    }
+}
+
+
+struct Maf<T> {
+    labels: Vec<T>,
+    initial: MafState
+}
+
+impl<T: Clone + Hash + Eq> Maf<T> {
+    fn new(t1: &IndexedTree<T>, t2: &IndexedTree<T>, unrooted: bool) -> Self {
+        let labels: Vec<&T> = t1.leaves().map(|id| t1.label(id).expect("unlabelled leaf")).collect();
+        let index: HashMap<&T, usize> = labels.iter().enumerate().map(|(i, &x)| (x, i)).collect();
+
+        assert_eq!(index.len(), labels.len(), "repeated leaf label");
+        assert_eq!(labels.len(), t2.leaves().count(), "trees have different leaf sets");
+
+        let rho = labels.len();
+        let root_leaf = if unrooted { None } else { Some(rho) };
+
+        Maf {
+            labels: labels.into_iter().cloned().collect(),
+            initial: MafState::new(
+                Forest::new(t1, &index, root_leaf),
+                Forest::new(t2, &index, root_leaf),
+                unrooted,
+                rho
+            )
+        }
+    }
+
+    // The components of the final forest: the one of the leaf standing for the root, without
+    // it, and the others.
+    fn components(&self, state: &MafState) -> (Option<Phylo<T>>, Vec<Phylo<T>>) {
+        let rho = self.labels.len();
+
+        let mut subtrees: Vec<(Option<Phylo<T>>, bool)> = self.labels.iter()
+            .map(|x| (Some(Phylo::leaf_with(x.clone())), false))
+            .chain(Some((None, true)))
+            .collect();
+
+        for &(a, c) in &state.merged {
+            let t = match (subtrees[a].0.clone(), subtrees[c].0.clone()) {
+                (Some(ta), Some(tc)) => Some(Phylo::node(vec![ta, tc])),
+                (ta, tc)             => ta.or(tc)
+            };
+
+            subtrees.push((t, subtrees[a].1 || subtrees[c].1));
+        }
+
+        let f2 = &state.f2;
+        let roots = (0..f2.children.len()).filter(|&v| f2.alive[v] && f2.parent[v].is_none());
+
+        let mut root = None;
+        let mut others = Vec::new();
+
+        for r in roots {
+            let mut stack = vec![(r, false)];
+            let mut values: Vec<Option<Phylo<T>>> = Vec::new();
+            let mut has_rho = false;
+
+            while let Some((v, expanded)) = stack.pop() {
+                if f2.is_leaf(v) {
+                    let l = f2.label[v].unwrap();
+                    has_rho |= l == rho || subtrees[l].1;
+                    values.push(subtrees[l].0.clone());
+                } else if !expanded {
+                    stack.push((v, true));
+                    stack.extend(f2.children(v).iter().rev().map(|&ch| (ch, false)));
+                } else {
+                    let start = values.len() - f2.children(v).len();
+                    let mut ts: Vec<Phylo<T>> = values.drain(start..).flatten().collect();
+
+                    values.push(match ts.len() {
+                        0 => None,
+                        1 => ts.pop(),
+                        _ => Some(Phylo::node(ts))
+                    });
+                }
+            }
+
+            let t = values.pop().unwrap();
+
+            if has_rho {
+                root = t;
+            } else {
+                others.extend(t);
+            }
+        }
+
+        (root, others)
+    }
+
+    // The best forest with fewer than `bound` cuts, if any.
+    fn search(&self, bound: u32) -> Option<MafState> {
+        MafState::best_below(self.initial.clone(), bound)
+    }
+
+    fn exact(&self) -> MafState {
+        let approx = self.initial.clone().approximate();
+        let bound = approx.cuts;
+
+        self.search(bound).unwrap_or(approx)
+    }
+
+    fn at_most(&self, max_cuts: u32) -> Option<MafState> {
+        let approx = self.initial.clone().approximate();
+
+        if approx.cuts <= max_cuts {
+            let bound = approx.cuts;
+            Some(self.search(bound).unwrap_or(approx))
+        } else {
+            self.search(max_cuts + 1)
+        }
+    }
+}
+
+
+// Common clusters of both trees split the rSPR distance into independent parts (Linz and
+// Semple 2011): every common cluster, with the largest common clusters below it replaced by
+// leaves, is compared on its own and the distances add up. When some maximum agreement forest
+// of a cluster cuts the edge above its root, the cluster has no part joined to the rest of the
+// tree, and its leaf is removed instead.
+struct Clusters {
+    // The maximum agreement forests of the clusters compared, from the leaves up, ending with
+    // the whole tree. Leaves are labelled by their position among the leaves of the first
+    // tree, and the leaf standing for the k-th cluster by n + k.
+    forests: Vec<Option<(Maf<usize>, MafState)>>,
+    joined: Vec<bool>
+}
+
+impl Clusters {
+    // Fails if the forests need more than `max_cuts` cuts in total.
+    fn new<T: Clone + Hash + Eq>(t1: &IndexedTree<T>, t2: &IndexedTree<T>, max_cuts: u32) -> Option<Self> {
+        let leaves: HashMap<&T, usize> = t1.leaves()
+            .enumerate()
+            .map(|(i, id)| (t1.label(id).expect("unlabelled leaf"), i))
+            .collect();
+
+        let n = leaves.len();
+        assert_eq!(n, t2.leaves().count(), "trees have different leaf sets");
+
+        let lca2 = Lca::new(t2);
+        let k1 = t1.kappas();
+        let k2 = t2.kappas();
+
+        // The lowest node of the second tree above the cluster of every node of the first.
+        let mut above = vec![t2.root(); t1.len()];
+        let mut cluster1 = vec![None; t1.len()];
+        let mut cluster2 = vec![None; t2.len()];
+        let mut tops = Vec::new();
+
+        let ids: Vec<NodeId> = t1.node_ids().collect();
+
+        for &u in ids.iter().rev() {
+            above[u.index()] = if t1.is_leaf(u) {
+                t2.find_leaf(t1.label(u).unwrap()).expect("trees have different leaf sets")
+            } else {
+                t1.children(u).map(|ch| above[ch.index()]).reduce(|x, y| lca2.lca(x, y)).unwrap()
+            };
+
+            let v = above[u.index()];
+
+            if !t1.is_leaf(u) && k1[u.index()] == k2[v.index()] {
+                cluster1[u.index()] = Some(tops.len());
+                cluster2[v.index()] = Some(tops.len());
+                tops.push((u, v));
+            }
+        }
+
+        let mut clusters = Clusters {
+            forests: Vec::with_capacity(tops.len()),
+            joined: Vec::with_capacity(tops.len())
+        };
+
+        let mut cuts = 0;
+
+        for (u, v) in tops {
+            let sub1 = clusters.restrict(t1, u, &cluster1, &leaves);
+            let sub2 = clusters.restrict(t2, v, &cluster2, &leaves);
+
+            let (sub1, sub2) = match (sub1, sub2) {
+                (Some(sub1), Some(sub2)) => (sub1.to_indexed(), sub2.to_indexed()),
+                _                        => {
+                    clusters.forests.push(None);
+                    clusters.joined.push(false);
+                    continue;
+                }
+            };
+
+            let maf = Maf::new(&sub1, &sub2, false);
+            let state = maf.at_most(max_cuts - cuts)?;
+
+            let rho = maf.labels.len();
+            let root_leaf = state.f2.node_of[rho];
+            let joined = !state.f2.alive[root_leaf] || state.f2.parent[root_leaf].is_some();
+
+            let (state, joined) = if joined {
+                let mut cut_root = maf.initial.clone();
+                cut_root.cut_all(&[root_leaf]);
+
+                match MafState::best_below(cut_root, state.cuts + 1) {
+                    Some(cut) => (cut, false),
+                    None      => (state, true)
+                }
+            } else {
+                (state, false)
+            };
+
+            cuts += state.cuts;
+            clusters.forests.push(Some((maf, state)));
+            clusters.joined.push(joined);
+        }
+
+        Some(clusters)
+    }
+
+    // The cluster below `top` with the clusters compared so far replaced by leaves or removed,
+    // or None if it has no leaves left.
+    fn restrict<T: Hash + Eq>(&self, t: &IndexedTree<T>, top: NodeId, cluster: &[Option<usize>], leaves: &HashMap<&T, usize>) -> Option<Phylo<usize>> {
+        let n = leaves.len();
+
+        let mut stack = vec![(top, false)];
+        let mut values: Vec<Option<Phylo<usize>>> = Vec::new();
+
+        while let Some((u, expanded)) = stack.pop() {
+            match cluster[u.index()] {
+                Some(k) if u != top => values.push(Some(Phylo::leaf_with(n + k)).filter(|_| self.joined[k])),
+
+                _ if t.is_leaf(u) => values.push(Some(Phylo::leaf_with(leaves[t.label(u).unwrap()]))),
+
+                _ if !expanded => {
+                    stack.push((u, true));
+                    let chs: Vec<NodeId> = t.children(u).collect();
+                    stack.extend(chs.into_iter().rev().map(|ch| (ch, false)));
+                },
+
+                _ => {
+                    let start = values.len() - t.num_children(u);
+                    let mut ts: Vec<Phylo<usize>> = values.drain(start..).flatten().collect();
+
+                    values.push(match ts.len() {
+                        0 => None,
+                        1 => ts.pop(),
+                        _ => Some(Phylo::node(ts))
+                    });
+                }
+            }
+        }
+
+        values.pop().unwrap()
+    }
+
+    fn cuts(&self) -> u32 {
+        self.forests.iter().flatten().map(|(_, state)| state.cuts).sum()
+    }
+
+    fn forest<T: Clone>(&self, leaves: &[&T]) -> Vec<Phylo<T>> {
+        let mut roots = Vec::new();
+        let mut forest = Vec::new();
+
+        for f in &self.forests {
+            let root = f.as_ref().and_then(|(maf, state)| {
+                let (root, others) = maf.components(state);
+
+                for t in others {
+                    forest.extend(expand(&t, leaves, &mut roots));
+                }
+
+                root.and_then(|t| expand(&t, leaves, &mut roots))
+            });
+
+            roots.push(root);
+        }
+
+        match roots.pop() {
+            Some(root) => forest.extend(root),
+            // A single leaf has no clusters to compare and is the only component.
+            None       => forest.extend(leaves.iter().map(|&x| Phylo::leaf_with(x.clone())))
+        }
+
+        forest
+    }
+}
+
+// The tree with every leaf standing for a cluster replaced by the component of the root of
+// that cluster.
+fn expand<T: Clone>(t: &Phylo<usize>, leaves: &[&T], roots: &mut [Option<Phylo<T>>]) -> Option<Phylo<T>> {
+    let n = leaves.len();
+
+    t.cata(
+        |&x| if x < n { Some(Phylo::leaf_with(leaves[x].clone())) } else { roots[x - n].take() },
+        |_, ts| {
+            let mut ts: Vec<Phylo<T>> = ts.flatten().collect();
+
+            match ts.len() {
+                0 => None,
+                1 => ts.pop(),
+                _ => Some(Phylo::node(ts))
+            }
+        })
+}
+
+
+impl<T: Clone + Hash + Eq> IndexedTree<T> {
+    // A maximum agreement forest of two rooted binary trees, found by branch and bound with
+    // the refined branching of Whidden, Beiko and Zeh in O(2.42^k) steps for every common
+    // cluster. It has one component more than the rSPR distance unless the root is one of the
+    // cuts.
+    pub fn maximum_agreement_forest(&self, other: &Self) -> Vec<Phylo<T>> {
+        let leaves: Vec<&T> = self.leaves().map(|id| self.label(id).unwrap()).collect();
+        Clusters::new(self, other, u32::MAX).unwrap().forest(&leaves)
+    }
+
+    pub fn rspr_distance(&self, other: &Self) -> u32 {
+        Clusters::new(self, other, u32::MAX).unwrap().cuts()
+    }
+
+    // An upper bound on the rSPR distance that is at most three times the distance, computed
+    // in polynomial time.
+    pub fn approx_rspr_distance(&self, other: &Self) -> u32 {
+        Maf::new(self, other, false).initial.approximate().cuts
+    }
+
+    // The rSPR distance if it is at most `max_distance`.
+    pub fn rspr_distance_at_most(&self, other: &Self, max_distance: u32) -> Option<u32> {
+        Clusters::new(self, other, max_distance).map(|c| c.cuts())
+    }
+
+    // A maximum agreement forest of two unrooted binary trees, given rooted with two or
+    // three children at the root, whose components are unrooted.
+    pub fn maximum_unrooted_agreement_forest(&self, other: &Self) -> Vec<Phylo<T>> {
+        let maf = Maf::new(self, other, true);
+        maf.components(&maf.exact()).1
+    }
+
+    // The TBR distance of two unrooted binary trees, one less than the number of components
+    // of their maximum unrooted agreement forests (Allen and Steel 2001).
+    pub fn tbr_distance(&self, other: &Self) -> u32 {
+        Maf::new(self, other, true).exact().cuts
+    }
+}
+
+impl<T: Clone + Hash + Eq> Phylo<T> {
+    pub fn rspr_distance(&self, other: &Self) -> u32 {
+        self.to_indexed().rspr_distance(&other.to_indexed())
+    }
+
+    pub fn approx_rspr_distance(&self, other: &Self) -> u32 {
+        self.to_indexed().approx_rspr_distance(&other.to_indexed())
+    }
+
+    pub fn maximum_agreement_forest(&self, other: &Self) -> Vec<Phylo<T>> {
+        self.to_indexed().maximum_agreement_forest(&other.to_indexed())
+    }
+
+    pub fn tbr_distance(&self, other: &Self) -> u32 {
+        self.to_indexed().tbr_distance(&other.to_indexed())
+    }
+
+    pub fn maximum_unrooted_agreement_forest(&self, other: &Self) -> Vec<Phylo<T>> {
+        self.to_indexed().maximum_unrooted_agreement_forest(&other.to_indexed())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet, VecDeque};
+    use std::collections::btree_map::Entry;
+
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    use phylo::{LabelledTrees, Phylo};
+
+    fn canonical(t: &Phylo<u32>) -> Phylo<u32> {
+        t.cata(|&x| Phylo::leaf_with(x), |_, chs| {
+            let mut ts: Vec<Phylo<u32>> = chs.collect();
+            ts.sort();
+            Phylo::node(ts)
+        })
+    }
+
+    // The nontrivial splits of the unrooted tree, each given by its side without leaf 0.
+    fn splits(t: &Phylo<u32>, n: u32) -> BTreeSet<Vec<u32>> {
+        let mut splits = BTreeSet::new();
+
+        t.cata(|&x| vec![x], |_, chs| {
+            let cluster: Vec<u32> = chs.flatten().collect();
+            let side: Vec<u32> = if cluster.contains(&0) {
+                (0..n).filter(|x| !cluster.contains(x)).collect()
+            } else {
+                cluster.clone()
+            };
+
+            if side.len() > 1 && side.len() + 1 < n as usize {
+                let mut side = side;
+                side.sort();
+                splits.insert(side);
+            }
+
+            cluster
+        });
+
+        splits
+    }
+
+    // Distances from `source` to the trees at most `radius` moves away, by breadth-first search
+    // over the moves given by `neighbours`, with trees identified by `key`.
+    fn bfs<K: Ord + Clone, F, N>(source: &Phylo<u32>, radius: u32, key: F, neighbours: N) -> BTreeMap<K, u32>
+        where F: Fn(&Phylo<u32>) -> K,
+              N: Fn(&Phylo<u32>) -> Vec<Phylo<u32>> {
+
+        let mut dist = BTreeMap::new();
+        let mut queue = VecDeque::new();
+
+        dist.insert(key(source), 0);
+        queue.push_back((source.clone(), 0));
+
+        while let Some((t, d)) = queue.pop_front() {
+            if d == radius {
+                continue;
+            }
+
+            for u in neighbours(&t) {
+                if let Entry::Vacant(entry) = dist.entry(key(&u)) {
+                    entry.insert(d + 1);
+                    queue.push_back((u, d + 1));
+                }
+            }
+        }
+
+        dist
+    }
+
+    #[test]
+    fn rspr_distance_matches_spr_moves() {
+        let mut rng = StdRng::seed_from_u64(45);
+
+        for n in 1..8u32 {
+            let labels: Vec<u32> = (0..n).collect();
+            let trees = LabelledTrees::binary(&labels);
+
+            // Every tree is searched up to six leaves, and those up to three moves away above.
+            let radius = if n < 7 { n } else { 3 };
+
+            let source = trees.unrank(rng.random_range(0..trees.num_trees()));
+            let dist = bfs(&source, radius, canonical, |t| t.spr_neighbours());
+
+            for _ in 0..60 {
+                let target = trees.unrank(rng.random_range(0..trees.num_trees()));
+                let d = source.rspr_distance(&target);
+
+                match dist.get(&canonical(&target)) {
+                    Some(&moves) => assert_eq!(d, moves, "{:?} {:?}", source, target),
+                    None         => assert!(n == 7 && d > radius, "{:?} {:?}", source, target)
+                }
+
+                let (i, j) = (source.to_indexed(), target.to_indexed());
+                assert_eq!(i.rspr_distance_at_most(&j, d), Some(d));
+                if d > 0 {
+                    assert_eq!(i.rspr_distance_at_most(&j, d - 1), None);
+                }
+
+                let forest = source.maximum_agreement_forest(&target);
+                let mut leaves: Vec<u32> = forest.iter().flat_map(|t| t.get_leaves()).cloned().collect();
+                leaves.sort();
+
+                assert_eq!(leaves, labels);
+                assert!(forest.len() as u32 == d || forest.len() as u32 == d + 1);
+                assert!(source.approx_rspr_distance(&target) <= 3 * d);
+            }
+        }
+    }
+
+    #[test]
+    fn tbr_distance_matches_tbr_moves() {
+        let mut rng = StdRng::seed_from_u64(46);
+
+        for n in 1..8u32 {
+            let labels: Vec<u32> = (0..n).collect();
+            let trees = LabelledTrees::binary(&labels);
+
+            let source = trees.unrank(rng.random_range(0..trees.num_trees()));
+            let dist = bfs(&source, n, |t| splits(t, n), |t| t.tbr_neighbours());
+
+            for _ in 0..60 {
+                let target = trees.unrank(rng.random_range(0..trees.num_trees()));
+                let d = dist[&splits(&target, n)];
+
+                assert_eq!(source.tbr_distance(&target), d, "{:?} {:?}", source, target);
+                assert!(d <= source.rspr_distance(&target));
+
+                let forest = source.maximum_unrooted_agreement_forest(&target);
+                let leaves: usize = forest.iter().map(|t| t.get_leaves().len()).sum();
+
+                assert_eq!(leaves, n as usize);
+                assert_eq!(forest.len() as u32, d + 1);
+            }
+        }
+    }
+}
@@ -0,0 +1,887 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::Add;
+
+use phylo::Phylo;
+use phylo::arena::{IndexedTree, NodeId};
+
+
+// Classification of the triplets (or quartets) of leaves according to their topologies in two
+// trees, where a topology is unresolved when its leaves meet at a single multifurcation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TopologyComparison {
+    pub total: u128,
+    pub resolved_same: u128,
+    pub resolved_different: u128,
+    pub resolved_only_first: u128,
+    pub resolved_only_second: u128,
+    pub unresolved_both: u128
+}
+
+impl TopologyComparison {
+    pub fn distance(&self) -> u128 {
+        self.total - self.resolved_same - self.unresolved_both
+    }
+
+    fn from_counts(total: u128, resolved1: u128, resolved2: u128, same: u128, unresolved_both: u128) -> Self {
+        let resolved_both = resolved1 + resolved2 + unresolved_both - total;
+
+        TopologyComparison {
+            total,
+            resolved_same: same,
+            resolved_different: resolved_both - same,
+            resolved_only_first: resolved1 - resolved_both,
+            resolved_only_second: resolved2 - resolved_both,
+            unresolved_both
+        }
+    }
+}
+
+impl Add for TopologyComparison {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        TopologyComparison {
+            total: self.total + other.total,
+            resolved_same: self.resolved_same + other.resolved_same,
+            resolved_different: self.resolved_different + other.resolved_different,
+            resolved_only_first: self.resolved_only_first + other.resolved_only_first,
+            resolved_only_second: self.resolved_only_second + other.resolved_only_second,
+            unresolved_both: self.unresolved_both + other.unresolved_both
+        }
+    }
+}
+
+
+fn choose3(n: u128) -> u128 {
+    if n < 3 { 0 } else { n*(n-1)*(n-2) / 6 }
+}
+
+fn choose4(n: u128) -> u128 {
+    if n < 4 { 0 } else { n*(n-1)*(n-2)*(n-3) / 24 }
+}
+
+// Sums of ranges of counts, with point updates.
+struct Fenwick(Vec<i128>);
+
+impl Fenwick {
+    fn new(len: usize) -> Self {
+        Fenwick(vec![0; len + 1])
+    }
+
+    fn add(&mut self, i: usize, delta: i128) {
+        let mut i = i + 1;
+        while i < self.0.len() {
+            self.0[i] += delta;
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    fn prefix(&self, mut i: usize) -> i128 {
+        let mut sum = 0;
+        while i > 0 {
+            sum += self.0[i];
+            i &= i - 1;
+        }
+        sum
+    }
+
+    fn range(&self, lo: usize, hi: usize) -> i128 {
+        self.prefix(hi) - self.prefix(lo)
+    }
+}
+
+// For every node w, the number l(w) of leaves in its light children and the number o(w) of
+// leaves under it outside the current cluster, with range updates of o and range sums of l
+// and of l*o.
+struct PathSums {
+    len: usize,
+    light: Vec<i128>,
+    product: Vec<i128>,
+    pending: Vec<i128>
+}
+
+impl PathSums {
+    fn new(len: usize) -> Self {
+        let size = 4 * len.max(1);
+        PathSums { len, light: vec![0; size], product: vec![0; size], pending: vec![0; size] }
+    }
+
+    fn apply(&mut self, x: usize, delta: i128) {
+        self.product[x] += delta * self.light[x];
+        self.pending[x] += delta;
+    }
+
+    fn push(&mut self, x: usize) {
+        let delta = self.pending[x];
+        if delta != 0 {
+            self.apply(2*x, delta);
+            self.apply(2*x + 1, delta);
+            self.pending[x] = 0;
+        }
+    }
+
+    fn pull(&mut self, x: usize) {
+        self.light[x] = self.light[2*x] + self.light[2*x + 1];
+        self.product[x] = self.product[2*x] + self.product[2*x + 1];
+    }
+
+    fn add_outside(&mut self, lo: usize, hi: usize, delta: i128) {
+        let len = self.len;
+        self.add_outside_at(1, 0, len, lo, hi, delta);
+    }
+
+    fn add_outside_at(&mut self, x: usize, l: usize, r: usize, lo: usize, hi: usize, delta: i128) {
+        if hi <= l || r <= lo {
+            return;
+        }
+        if lo <= l && r <= hi {
+            self.apply(x, delta);
+            return;
+        }
+
+        self.push(x);
+        let mid = (l + r) / 2;
+        self.add_outside_at(2*x, l, mid, lo, hi, delta);
+        self.add_outside_at(2*x + 1, mid, r, lo, hi, delta);
+        self.pull(x);
+    }
+
+    // Adds to l(w), where o(w) is `outside`.
+    fn add_light(&mut self, w: usize, delta: i128, outside: i128) {
+        let (mut x, mut l, mut r) = (1, 0, self.len);
+        let mut path = Vec::new();
+
+        while r - l > 1 {
+            self.push(x);
+            path.push(x);
+
+            let mid = (l + r) / 2;
+            if w < mid {
+                x *= 2;
+                r = mid;
+            } else {
+                x = 2*x + 1;
+                l = mid;
+            }
+        }
+
+        self.light[x] += delta;
+        self.product[x] += delta * outside;
+
+        for &y in path.iter().rev() {
+            self.pull(y);
+        }
+    }
+
+    fn sum(&mut self, lo: usize, hi: usize) -> (i128, i128) {
+        let len = self.len;
+        self.sum_at(1, 0, len, lo, hi)
+    }
+
+    fn sum_at(&mut self, x: usize, l: usize, r: usize, lo: usize, hi: usize) -> (i128, i128) {
+        if hi <= l || r <= lo {
+            return (0, 0);
+        }
+        if lo <= l && r <= hi {
+            return (self.light[x], self.product[x]);
+        }
+
+        self.push(x);
+        let mid = (l + r) / 2;
+        let (a, b) = self.sum_at(2*x, l, mid, lo, hi);
+        let (c, d) = self.sum_at(2*x + 1, mid, r, lo, hi);
+        (a + c, b + d)
+    }
+}
+
+// The second tree numbered so that every subtree and every heavy path (through the children
+// with most leaves) is an interval, with its leaves coloured as joined when they lie in an
+// earlier child of the current node u of the first tree, and outside when they lie outside
+// its cluster. The other leaves of the cluster are only inside.
+struct ColouredTree {
+    parent: Vec<Option<usize>>,
+    head: Vec<usize>,
+    end: Vec<usize>,
+    joined: Fenwick,
+    outside: Fenwick,
+    total_outside: i128,
+    // Over the light children x of every node: joined and outside leaves, and the sum of
+    // their products.
+    light_joined: Vec<i128>,
+    light_outside: Vec<i128>,
+    light_products: Vec<i128>,
+    // The pairs of a joined and an outside leaf in different light children of every node.
+    fans: Fenwick,
+    paths: PathSums
+}
+
+impl ColouredTree {
+    // The tree, and the position of each of its leaves.
+    fn new<T>(t: &IndexedTree<T>) -> (Self, Vec<(NodeId, usize)>) {
+        let kappas = t.kappas();
+        let pre_order = t.pre_order();
+
+        let mut sizes = vec![1; t.len()];
+        for &v in pre_order.iter().rev() {
+            if let Some(p) = t.parent(v) {
+                sizes[p.index()] += sizes[v.index()];
+            }
+        }
+
+        let len = pre_order.len();
+        let mut tree = ColouredTree {
+            parent: vec![None; len],
+            head: vec![0; len],
+            end: vec![0; len],
+            joined: Fenwick::new(len),
+            outside: Fenwick::new(len),
+            total_outside: 0,
+            light_joined: vec![0; len],
+            light_outside: vec![0; len],
+            light_products: vec![0; len],
+            fans: Fenwick::new(len),
+            paths: PathSums::new(len)
+        };
+
+        let mut leaves = Vec::new();
+        let mut next = 0;
+        let mut stack = vec![(t.root(), None, false)];
+
+        while let Some((v, parent, heavy)) = stack.pop() {
+            let pos = next;
+            next += 1;
+
+            tree.parent[pos] = parent;
+            tree.head[pos] = match parent {
+                Some(w) if heavy => tree.head[w],
+                _                => pos
+            };
+            tree.end[pos] = pos + sizes[v.index()];
+
+            if t.is_leaf(v) {
+                tree.outside.add(pos, 1);
+                tree.total_outside += 1;
+                leaves.push((v, pos));
+                continue;
+            }
+
+            let heavy = t.children(v).max_by_key(|ch| kappas[ch.index()]).unwrap();
+
+            for ch in t.children(v).filter(|&ch| ch != heavy) {
+                tree.light_outside[pos] += kappas[ch.index()] as i128;
+                stack.push((ch, Some(pos), false));
+            }
+            // Visited next, so that heavy paths are intervals.
+            stack.push((heavy, Some(pos), true));
+        }
+
+        (tree, leaves)
+    }
+
+    fn counts(&self, w: usize) -> (i128, i128) {
+        (self.joined.range(w, self.end[w]), self.outside.range(w, self.end[w]))
+    }
+
+    // Recolours the leaf at position `pos`.
+    fn update(&mut self, pos: usize, joined: i128, outside: i128) {
+        self.joined.add(pos, joined);
+        self.outside.add(pos, outside);
+        self.total_outside += outside;
+
+        let mut y = pos;
+        loop {
+            let h = self.head[y];
+            self.paths.add_outside(h, y + 1, outside);
+
+            let w = match self.parent[h] {
+                Some(w) => w,
+                None    => break
+            };
+
+            let (b, o) = self.counts(h);
+            let fans = self.light_joined[w]*self.light_outside[w] - self.light_products[w];
+
+            self.light_joined[w] += joined;
+            self.light_outside[w] += outside;
+            self.light_products[w] += b*o - (b - joined)*(o - outside);
+
+            // The outside leaves of w before the update of its own heavy path.
+            let ow = self.outside.range(w, self.end[w]) - outside;
+            self.paths.add_light(w, joined, ow);
+
+            let delta = self.light_joined[w]*self.light_outside[w] - self.light_products[w] - fans;
+            self.fans.add(w, delta);
+
+            y = w;
+        }
+    }
+
+    // For the leaf a at position `pos`, the pairs of a joined leaf b and an outside leaf c
+    // for which this tree has the triplet ab|c, and those for which it has a fan.
+    fn query(&mut self, pos: usize) -> (i128, i128) {
+        let mut same = 0;
+        let mut fans = 0;
+
+        let mut x = pos;
+        loop {
+            // Ancestors of a on this heavy path, with a under their heavy child.
+            let h = self.head[x];
+            if h < x {
+                let (l, lo) = self.paths.sum(h, x);
+                same += self.total_outside*l - lo;
+                fans += self.fans.range(h, x);
+            }
+
+            let w = match self.parent[h] {
+                Some(w) => w,
+                None    => break
+            };
+
+            // The parent of the head, with a under a light child.
+            let (bw, ow) = self.counts(w);
+            let (bh, oh) = self.counts(h);
+            let (bc, oc) = self.counts(w + 1);
+
+            same += (bw - bh) * (self.total_outside - ow);
+            fans += (bw - bh)*(ow - oh) - (self.light_products[w] + bc*oc - bh*oh);
+
+            x = w;
+        }
+
+        (same, fans)
+    }
+}
+
+// The triplets ab|c of a tree with a and b joined at each node, summed over its nodes.
+fn resolved_triplets<T>(t: &IndexedTree<T>, kappas: &[u32]) -> u128 {
+    let n = kappas[t.root().index()] as u128;
+
+    t.node_ids()
+        .map(|u| {
+            let k = kappas[u.index()] as u128;
+            let squares: u128 = t.children(u).map(|ch| (kappas[ch.index()] as u128).pow(2)).sum();
+
+            (k*k - squares) / 2 * (n - k)
+        })
+        .sum()
+}
+
+// Every triplet ab|c of the first tree has a and b in different children of u = lca1(a, b)
+// and c outside its cluster. The children of every u are visited smallest first, each
+// colouring its leaves in the second tree only while it is visited, except for the largest,
+// which keeps them for u. Then, one child at a time, the leaves a of every other child count
+// the pairs b, c of leaves joined in an earlier child and outside of u, by their positions
+// along the path from a to the root of the second tree. Every leaf is recoloured O(log n)
+// times, and each recolouring or count walks O(log n) heavy paths in O(log n) time, for
+// O(n log^3 n) time overall.
+fn compare_triplets<T>(t1: &IndexedTree<T>, t2: &IndexedTree<T>) -> TopologyComparison
+    where T: Clone + Hash + Eq {
+
+    let (mut coloured, leaves2) = ColouredTree::new(t2);
+    let positions: HashMap<&T, usize> = leaves2.iter()
+        .map(|&(id, pos)| (t2.label(id).expect("unlabelled leaf"), pos))
+        .collect();
+
+    let n = positions.len();
+    assert_eq!(n, t1.leaves().count(), "trees have different leaf sets");
+
+    let k1 = t1.kappas();
+
+    // The positions in the second tree of the leaves of the first in pre-order, so that those
+    // of every cluster are an interval starting at `first`.
+    let mut order = Vec::with_capacity(n);
+    let mut first = vec![0; t1.len()];
+    for u in t1.pre_order() {
+        first[u.index()] = order.len();
+
+        if t1.is_leaf(u) {
+            let x = t1.label(u).expect("unlabelled leaf");
+            order.push(*positions.get(x).expect("trees have different leaf sets"));
+        }
+    }
+
+    let mut same = 0i128;
+    let mut fans = 0i128;
+
+    let mut stack = vec![(t1.root(), true, false)];
+
+    while let Some((u, keep, expanded)) = stack.pop() {
+        let start = first[u.index()];
+        let leaves = &order[start..start + k1[u.index()] as usize];
+
+        if t1.is_leaf(u) {
+            if keep {
+                coloured.update(leaves[0], 1, -1);
+            }
+            continue;
+        }
+
+        let heavy = t1.children(u).max_by_key(|ch| k1[ch.index()]).unwrap();
+        let light: Vec<NodeId> = t1.children(u).filter(|&ch| ch != heavy).collect();
+
+        if !expanded {
+            stack.push((u, keep, true));
+            stack.push((heavy, true, false));
+            stack.extend(light.iter().map(|&ch| (ch, false, false)));
+            continue;
+        }
+
+        let cluster = |ch: NodeId| {
+            let start = first[ch.index()];
+            &order[start..start + k1[ch.index()] as usize]
+        };
+
+        for &ch in &light {
+            for &pos in cluster(ch) {
+                coloured.update(pos, 0, -1);
+            }
+        }
+
+        for &ch in &light {
+            for &pos in cluster(ch) {
+                let (s, f) = coloured.query(pos);
+                same += s;
+                fans += f;
+            }
+            for &pos in cluster(ch) {
+                coloured.update(pos, 1, 0);
+            }
+        }
+
+        if !keep {
+            for &pos in leaves {
+                coloured.update(pos, -1, 1);
+            }
+        }
+    }
+
+    let resolved1 = resolved_triplets(t1, &k1);
+    let resolved2 = resolved_triplets(t2, &t2.kappas());
+    let resolved_both = resolved1 - fans as u128;
+
+    let total = choose3(n as u128);
+    TopologyComparison::from_counts(total, resolved1, resolved2, same as u128, total + resolved_both - resolved1 - resolved2)
+}
+
+// The resolved quartets of a tree, each found at the two nodes where its pairs of leaves
+// are joined, from the sizes of the branches of every node.
+fn resolved_quartets<T>(t: &IndexedTree<T>, kappas: &[u32]) -> u128 {
+    let n = kappas[t.root().index()] as u128;
+
+    let twice: u128 = t.node_ids()
+        .filter(|&u| !t.is_leaf(u))
+        .map(|u| {
+            let mut s: Vec<u128> = t.children(u).map(|ch| kappas[ch.index()] as u128).collect();
+            if u != t.root() {
+                s.push(n - kappas[u.index()] as u128);
+            }
+
+            let total: u128 = s.iter().sum();
+            let e2 = (total*total - s.iter().map(|x| x*x).sum::<u128>()) / 2;
+            let pairs: u128 = s.iter().map(|&x| x*x.saturating_sub(1) / 2).sum();
+            let overlap: u128 = s.iter().map(|&x| x * (x*x.saturating_sub(1) / 2) * (total - x)).sum();
+
+            e2*pairs - overlap
+        })
+        .sum();
+
+    twice / 2
+}
+
+// With m[i*c + p] the number of leaves in branch i of a node u of the first tree and branch
+// p of a node v of the second, the quartets ab|cd of both trees with a and b joined at u and
+// at v, and the quartets ab|cd of the first tree with a and b joined at u whose leaves lie in
+// four different branches of v. Pairs of leaves in different rows and columns are counted by
+// inclusion-exclusion, in O(1) for every excluded row and column.
+fn junction_quartets(m: &[i128], r: usize, c: usize) -> (i128, i128) {
+    let at = |i: usize, p: usize| m[i*c + p];
+
+    let rows: Vec<i128> = (0..r).map(|i| (0..c).map(|p| at(i, p)).sum()).collect();
+    let cols: Vec<i128> = (0..c).map(|p| (0..r).map(|i| at(i, p)).sum()).collect();
+
+    let s: i128 = rows.iter().sum();
+    let rr: i128 = rows.iter().map(|x| x*x).sum();
+    let cc: i128 = cols.iter().map(|x| x*x).sum();
+    let q: i128 = m.iter().map(|x| x*x).sum();
+
+    let rho: Vec<i128> = (0..c).map(|p| (0..r).map(|i| rows[i] * at(i, p)).sum()).collect();
+    let alpha: Vec<i128> = (0..c).map(|p| (0..r).map(|i| at(i, p) * at(i, p)).sum()).collect();
+    let sigma: Vec<i128> = (0..r).map(|i| (0..c).map(|p| cols[p] * at(i, p)).sum()).collect();
+    let beta: Vec<i128> = (0..r).map(|i| (0..c).map(|p| at(i, p) * at(i, p)).sum()).collect();
+
+    // c and d in the same cell, a and b outside its row and column.
+    let mut same = 0;
+
+    for i in 0..r {
+        for p in 0..c {
+            let x = at(i, p);
+            if x < 2 {
+                continue;
+            }
+
+            let s1 = s - rows[i] - cols[p] + x;
+            let rr1 = rr - 2*rho[p] + alpha[p] - (rows[i] - x) * (rows[i] - x);
+            let cc1 = cc - 2*sigma[i] + beta[i] - (cols[p] - x) * (cols[p] - x);
+            let q1 = q - alpha[p] - beta[i] + x*x;
+
+            same += x*(x-1)/2 * (s1*s1 - rr1 - cc1 + q1) / 2;
+        }
+    }
+
+    if r < 3 || c < 4 {
+        return (same, 0);
+    }
+
+    // c and d in the same row and different columns, a and b outside them. Twice the sum over
+    // pairs of columns p, q of the pairs of leaves outside them, in different rows and
+    // columns, splits into terms of p and q alone and a term for the leaves of p and q in the
+    // same row, which for all rows together counts the rectangles of the matrix.
+    let mut twice_star = 0;
+
+    for i in 0..r {
+        let w: Vec<i128> = (0..c).map(|p| at(i, p)).collect();
+        let w1: i128 = w.iter().sum();
+        let w2: i128 = w.iter().map(|x| x*x).sum();
+
+        let s1 = s - rows[i];
+        let cols1: Vec<i128> = (0..c).map(|p| cols[p] - w[p]).collect();
+        let k = s1*s1 - (rr - rows[i]*rows[i]) - cols1.iter().map(|x| x*x).sum::<i128>() + (q - beta[i]);
+
+        let single: i128 = (0..c)
+            .map(|p| {
+                let h = -2*s1*cols1[p] + 2*cols1[p]*cols1[p] + 2*(rho[p] - rows[i]*w[p]) - 2*(alpha[p] - w[p]*w[p]);
+                w[p] * h * (w1 - w[p])
+            })
+            .sum();
+
+        let wc: i128 = (0..c).map(|p| w[p] * cols1[p]).sum();
+        let wc2: i128 = (0..c).map(|p| w[p]*w[p] * cols1[p]*cols1[p]).sum();
+
+        twice_star += k * (w1*w1 - w2) / 2 + single + wc*wc - wc2;
+    }
+
+    // The Gram matrix of the rows or of the columns, from the products of nonzero entries in
+    // the same column or row, whichever has fewer of them.
+    let by_rows: Vec<Vec<(usize, i128)>> = (0..r)
+        .map(|i| (0..c).filter(|&p| at(i, p) != 0).map(|p| (p, at(i, p))).collect())
+        .collect();
+    let by_cols: Vec<Vec<(usize, i128)>> = (0..c)
+        .map(|p| (0..r).filter(|&i| at(i, p) != 0).map(|i| (i, at(i, p))).collect())
+        .collect();
+
+    let cost = |lines: &[Vec<(usize, i128)>]| lines.iter().map(|l| l.len() * l.len()).sum::<usize>();
+    let lines = if cost(&by_rows) <= cost(&by_cols) { &by_rows } else { &by_cols };
+
+    let mut products: HashMap<(usize, usize), i128> = HashMap::new();
+    for line in lines {
+        for &(i, x) in line {
+            for &(j, y) in line {
+                *products.entry((i, j)).or_insert(0) += x*y;
+            }
+        }
+    }
+
+    let gram: i128 = products.values().map(|g| g*g).sum();
+
+    let rectangles = gram - beta.iter().map(|x| x*x).sum::<i128>() - alpha.iter().map(|x| x*x).sum::<i128>()
+        + m.iter().map(|x| x*x*x*x).sum::<i128>();
+
+    (same, (twice_star - rectangles) / 2)
+}
+
+// Every resolved quartet ab|cd of a tree is found at the node joining a to b, which has c
+// and d in a third branch, and at the node joining c to d. For every pair of nodes u, v of the
+// two trees, the overlaps of their branches give the quartets found at both, and those found
+// at u that are stars centred at v. Runs in O(n^2) time for trees of bounded degree; the
+// rectangles add at most a factor of the largest degree otherwise. Unlike triplets, quartets
+// are not compared in subquadratic time: that needs the hierarchical decompositions of Brodal
+// et al. for both trees, and the pairs of nodes here are kept for their simplicity.
+fn compare_quartets<T>(t1: &IndexedTree<T>, t2: &IndexedTree<T>) -> TopologyComparison
+    where T: Clone + Hash + Eq {
+
+    let leaves2: HashMap<&T, NodeId> = t2.leaves()
+        .map(|id| (t2.label(id).expect("unlabelled leaf"), id))
+        .collect();
+
+    let n = leaves2.len() as u128;
+    assert_eq!(n as usize, t1.leaves().count(), "trees have different leaf sets");
+
+    let k1 = t1.kappas();
+    let k2 = t2.kappas();
+
+    // Nodes of the second tree with at least three branches, with their children.
+    let nodes2: Vec<(NodeId, Vec<NodeId>)> = t2.node_ids()
+        .map(|v| (v, t2.children(v).collect::<Vec<_>>()))
+        .filter(|(v, chs)| chs.len() + (*v != t2.root()) as usize >= 3)
+        .collect();
+
+    let mut same = 0i128;
+    let mut star2 = 0i128;
+    let mut m = Vec::new();
+
+    let mut values: Vec<(Vec<u32>, NodeId)> = Vec::new();
+    let mut stack = vec![(t1.root(), Vec::new(), false)];
+
+    while let Some((u, mut pending, expanded)) = stack.pop() {
+        if !expanded {
+            let mut chs: Vec<NodeId> = t1.children(u).collect();
+            chs.sort_by_key(|ch| k1[ch.index()]);
+
+            pending = chs;
+        }
+
+        if let Some(ch) = pending.pop() {
+            stack.push((u, pending, true));
+            stack.push((ch, Vec::new(), false));
+            continue;
+        }
+
+        if t1.is_leaf(u) {
+            let x = t1.label(u).expect("unlabelled leaf");
+            let x2 = *leaves2.get(x).expect("trees have different leaf sets");
+
+            let mut row = vec![0; t2.len()];
+            row[x2.index()] = 1;
+            for w in t2.ancestors(x2) {
+                row[w.index()] = 1;
+            }
+
+            values.push((row, u));
+            continue;
+        }
+
+        let k = t1.num_children(u);
+        let start = values.len() - k;
+        let chs: Vec<(Vec<u32>, NodeId)> = values.drain(start..).collect();
+
+        let mut row = vec![0; t2.len()];
+        for (r, _) in &chs {
+            for (x, y) in row.iter_mut().zip(r) {
+                *x += y;
+            }
+        }
+
+        let up1 = u != t1.root();
+        let r = k + up1 as usize;
+
+        if r >= 3 {
+            let ku = k1[u.index()] as i128;
+
+            for (v, chs2) in &nodes2 {
+                let up2 = *v != t2.root();
+                let c = chs2.len() + up2 as usize;
+                let kv = k2[v.index()] as i128;
+
+                m.clear();
+
+                for (rc, ch) in &chs {
+                    m.extend(chs2.iter().map(|y| rc[y.index()] as i128));
+                    if up2 {
+                        m.push(k1[ch.index()] as i128 - rc[v.index()] as i128);
+                    }
+                }
+
+                if up1 {
+                    m.extend(chs2.iter().map(|y| k2[y.index()] as i128 - row[y.index()] as i128));
+                    if up2 {
+                        m.push(n as i128 - ku - kv + row[v.index()] as i128);
+                    }
+                }
+
+                let (s, st) = junction_quartets(&m, r, c);
+                same += s;
+                star2 += st;
+            }
+        }
+
+        values.push((row, u));
+    }
+
+    let resolved1 = resolved_quartets(t1, &k1);
+    let resolved2 = resolved_quartets(t2, &k2);
+
+    let same = (same / 2) as u128;
+    let resolved_both = resolved1 - (star2 / 2) as u128;
+
+    let total = choose4(n);
+    TopologyComparison::from_counts(total, resolved1, resolved2, same, total + resolved_both - resolved1 - resolved2)
+}
+
+
+impl<T: Clone + Hash + Eq> IndexedTree<T> {
+    pub fn triplet_comparison(&self, other: &Self) -> TopologyComparison {
+        compare_triplets(self, other)
+    }
+
+    pub fn quartet_comparison(&self, other: &Self) -> TopologyComparison {
+        compare_quartets(self, other)
+    }
+}
+
+impl<T: Clone + Hash + Eq> Phylo<T> {
+    pub fn triplet_distance(&self, other: &Self) -> u128 {
+        self.to_indexed().triplet_comparison(&other.to_indexed()).distance()
+    }
+
+    pub fn quartet_distance(&self, other: &Self) -> u128 {
+        self.to_indexed().quartet_comparison(&other.to_indexed()).distance()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    use phylo::{IndexedTree, LabelledTrees, Lca, Phylo};
+    use super::TopologyComparison;
+
+    fn random_tree<R: Rng>(trees: &LabelledTrees<u32>, rng: &mut R) -> IndexedTree<u32> {
+        let rank = rng.random_range(0..trees.num_trees());
+        trees.unrank(rank).to_indexed()
+    }
+
+    // A tree of n leaves joined two or three at a time, at random.
+    fn random_joins<R: Rng>(n: u32, rng: &mut R) -> IndexedTree<u32> {
+        let mut trees: Vec<Phylo<u32>> = (0..n).map(Phylo::leaf_with).collect();
+
+        while trees.len() > 1 {
+            let k = if trees.len() > 2 { rng.random_range(2..4) } else { 2 };
+            let children = (0..k).map(|_| {
+                let i = rng.random_range(0..trees.len());
+                trees.swap_remove(i)
+            }).collect();
+
+            trees.push(Phylo::node(children));
+        }
+
+        trees[0].to_indexed()
+    }
+
+    fn caterpillar(labels: &[u32]) -> Phylo<u32> {
+        labels[1..].iter().fold(Phylo::leaf_with(labels[0]), |t, &x| Phylo::node(vec![t, Phylo::leaf_with(x)]))
+    }
+
+    // The index of the resolved topology, or None for a fan or star.
+    fn topology(values: [u32; 3], largest: bool) -> Option<usize> {
+        let best = if largest { *values.iter().max().unwrap() } else { *values.iter().min().unwrap() };
+
+        if values.iter().filter(|&&x| x == best).count() == 1 {
+            values.iter().position(|&x| x == best)
+        } else {
+            None
+        }
+    }
+
+    fn classify(comparison: &mut TopologyComparison, t1: Option<usize>, t2: Option<usize>) {
+        comparison.total += 1;
+
+        match (t1, t2) {
+            (Some(x), Some(y)) if x == y => comparison.resolved_same += 1,
+            (Some(_), Some(_))           => comparison.resolved_different += 1,
+            (Some(_), None)              => comparison.resolved_only_first += 1,
+            (None, Some(_))              => comparison.resolved_only_second += 1,
+            (None, None)                 => comparison.unresolved_both += 1
+        }
+    }
+
+    fn brute_force_triplets(t1: &IndexedTree<u32>, t2: &IndexedTree<u32>, n: u32) -> TopologyComparison {
+        let (l1, l2) = (Lca::new(t1), Lca::new(t2));
+        let mut comparison = TopologyComparison::default();
+
+        let topology_in = |t: &IndexedTree<u32>, lca: &Lca<u32>, a, b, c| {
+            let (a, b, c) = (t.find_leaf(&a).unwrap(), t.find_leaf(&b).unwrap(), t.find_leaf(&c).unwrap());
+            topology([lca.depth(lca.lca(a, b)), lca.depth(lca.lca(a, c)), lca.depth(lca.lca(b, c))], true)
+        };
+
+        for a in 0..n {
+            for b in a+1..n {
+                for c in b+1..n {
+                    classify(&mut comparison, topology_in(t1, &l1, a, b, c), topology_in(t2, &l2, a, b, c));
+                }
+            }
+        }
+
+        comparison
+    }
+
+    fn brute_force_quartets(t1: &IndexedTree<u32>, t2: &IndexedTree<u32>, n: u32) -> TopologyComparison {
+        let (l1, l2) = (Lca::new(t1), Lca::new(t2));
+        let mut comparison = TopologyComparison::default();
+
+        let topology_in = |lca: &Lca<u32>, a, b, c, d| {
+            let dist = |x, y| lca.path_length_between_leaves(&x, &y).unwrap();
+            topology([dist(a, b) + dist(c, d), dist(a, c) + dist(b, d), dist(a, d) + dist(b, c)], false)
+        };
+
+        for a in 0..n {
+            for b in a+1..n {
+                for c in b+1..n {
+                    for d in c+1..n {
+                        classify(&mut comparison, topology_in(&l1, a, b, c, d), topology_in(&l2, a, b, c, d));
+                    }
+                }
+            }
+        }
+
+        comparison
+    }
+
+    #[test]
+    fn triplets_and_quartets_match_brute_force() {
+        let mut rng = StdRng::seed_from_u64(36);
+
+        for n in 3..9u32 {
+            let labels: Vec<u32> = (0..n).collect();
+            let binary = LabelledTrees::binary(&labels);
+            let all = LabelledTrees::all(&labels);
+
+            for i in 0..40 {
+                let trees = if i % 2 == 0 { &binary } else { &all };
+                let t1 = random_tree(trees, &mut rng);
+                let t2 = if i % 5 == 0 { t1.clone() } else { random_tree(&all, &mut rng) };
+
+                assert_eq!(t1.triplet_comparison(&t2), brute_force_triplets(&t1, &t2, n));
+                assert_eq!(t1.quartet_comparison(&t2), brute_force_quartets(&t1, &t2, n));
+            }
+        }
+    }
+
+    #[test]
+    fn triplets_of_larger_trees_match_brute_force() {
+        let mut rng = StdRng::seed_from_u64(36);
+
+        for &n in &[20, 40, 60] {
+            for _ in 0..5 {
+                let (t1, t2) = (random_joins(n, &mut rng), random_joins(n, &mut rng));
+                assert_eq!(t1.triplet_comparison(&t2), brute_force_triplets(&t1, &t2, n));
+            }
+        }
+    }
+
+    #[test]
+    fn triplets_of_opposite_caterpillars_all_differ() {
+        let n = 3000u32;
+        let labels: Vec<u32> = (0..n).collect();
+        let reversed: Vec<u32> = labels.iter().rev().cloned().collect();
+
+        let (t1, t2) = (caterpillar(&labels), caterpillar(&reversed));
+        let total = (n as u128) * (n as u128 - 1) * (n as u128 - 2) / 6;
+
+        assert_eq!(t1.triplet_distance(&t1), 0);
+        assert_eq!(t1.triplet_distance(&t2), total);
+    }
+
+    #[test]
+    fn distances_of_identical_trees_are_zero() {
+        let t = Phylo::node(vec![
+            Phylo::node(vec![Phylo::leaf_with(0u32), Phylo::leaf_with(1), Phylo::leaf_with(2)]),
+            Phylo::node(vec![Phylo::leaf_with(3), Phylo::node(vec![Phylo::leaf_with(4), Phylo::leaf_with(5)])])
+        ]);
+
+        assert_eq!(t.triplet_distance(&t), 0);
+        assert_eq!(t.quartet_distance(&t), 0);
+    }
+}
@@ -0,0 +1,501 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::Hash;
+
+use phylo::Phylo;
+use phylo::arena::{IndexedTree, NodeId};
+use phylo::newick::{ToNewick, ToNewickLeaf};
+
+
+// A tree without a root, stored as adjacency lists with the length of every edge. Converting
+// it back to a rooted tree hangs it from its anchor, which is the node where the root was
+// collapsed when it came from a rooted tree.
+#[derive(Clone, Debug)]
+pub struct UnrootedTree<T> {
+    labels: Vec<Option<T>>,
+    adjacency: Vec<Vec<(NodeId, Option<f64>)>>,
+    anchor: NodeId
+}
+
+fn join_lengths(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a + b),
+        (a, b)             => a.or(b)
+    }
+}
+
+impl<T: Clone> UnrootedTree<T> {
+    // Forgets the root of a tree. A root with two children is removed, joining the edges to
+    // them.
+    pub fn from_rooted(tree: &IndexedTree<T>) -> Self {
+        let root = tree.root();
+        let chs: Vec<NodeId> = tree.children(root).collect();
+        let drop_root = chs.len() == 2;
+
+        let new_id = |id: NodeId| NodeId::from_index(if drop_root { id.index() - 1 } else { id.index() });
+        let n = if drop_root { tree.len() - 1 } else { tree.len() };
+
+        let mut unrooted = UnrootedTree {
+            labels: tree.node_ids().skip(if drop_root { 1 } else { 0 }).map(|id| tree.label(id).cloned()).collect(),
+            adjacency: vec![Vec::new(); n],
+            anchor: NodeId::from_index(0)
+        };
+
+        // The edge joining the children of a removed root comes first in the anchor, in place
+        // of the edge to its parent.
+        if drop_root {
+            let (a, b) = (chs[0], chs[1]);
+            let length = join_lengths(tree.length(a), tree.length(b));
+            unrooted.add_edge(new_id(a), new_id(b), length);
+
+            let inner = if tree.is_leaf(a) { b } else { a };
+            unrooted.anchor = new_id(inner);
+        }
+
+        for id in tree.node_ids() {
+            if let Some(p) = tree.parent(id) {
+                if !(drop_root && p == root) {
+                    unrooted.add_edge(new_id(p), new_id(id), tree.length(id));
+                }
+            }
+        }
+
+        unrooted
+    }
+
+    fn add_edge(&mut self, u: NodeId, v: NodeId, length: Option<f64>) {
+        self.adjacency[u.index()].push((v, length));
+        self.adjacency[v.index()].push((u, length));
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    pub fn node_ids(&self) -> impl Iterator<Item=NodeId> {
+        (0..self.labels.len()).map(NodeId::from_index)
+    }
+
+    pub fn label(&self, id: NodeId) -> Option<&T> {
+        self.labels[id.index()].as_ref()
+    }
+
+    pub fn degree(&self, id: NodeId) -> usize {
+        self.adjacency[id.index()].len()
+    }
+
+    pub fn is_leaf(&self, id: NodeId) -> bool {
+        self.degree(id) <= 1
+    }
+
+    pub fn neighbours(&self, id: NodeId) -> impl Iterator<Item=NodeId> + '_ {
+        self.adjacency[id.index()].iter().map(|&(v, _)| v)
+    }
+
+    pub fn edge_length(&self, u: NodeId, v: NodeId) -> Option<f64> {
+        self.adjacency[u.index()].iter()
+            .find(|&&(w, _)| w == v)
+            .expect("nodes are not adjacent")
+            .1
+    }
+
+    // Every edge once, as a pair of nodes with the smaller id first.
+    pub fn edges(&self) -> Vec<(NodeId, NodeId, Option<f64>)> {
+        self.node_ids()
+            .flat_map(|u| self.adjacency[u.index()].iter()
+                .filter(move |&&(v, _)| u < v)
+                .map(move |&(v, l)| (u, v, l)))
+            .collect()
+    }
+
+    pub fn leaves(&self) -> impl Iterator<Item=NodeId> + '_ {
+        self.node_ids().filter(move |&id| self.is_leaf(id))
+    }
+
+    pub fn find_leaf(&self, label: &T) -> Option<NodeId>
+        where T: PartialEq {
+
+        self.leaves().find(|&id| self.label(id) == Some(label))
+    }
+
+    // Copies the part of the tree reached from `start` without crossing `from` below
+    // `parent`, in pre-order, recording the id in `tree` of every node copied.
+    fn hang_into(&self,
+                 tree: &mut IndexedTree<T>,
+                 ids: &mut [Option<NodeId>],
+                 start: NodeId,
+                 from: Option<NodeId>,
+                 parent: Option<NodeId>,
+                 length: Option<f64>) {
+
+        let mut stack = vec![(start, from, parent, length)];
+
+        while let Some((u, from, parent, length)) = stack.pop() {
+            let id = tree.add_node(parent, self.labels[u.index()].clone());
+            if parent.is_some() {
+                tree.set_length(id, length);
+            }
+            ids[u.index()] = Some(id);
+
+            stack.extend(self.adjacency[u.index()].iter().rev()
+                .filter(|&&(v, _)| Some(v) != from)
+                .map(|&(v, l)| (v, Some(u), Some(id), l)));
+        }
+    }
+
+    // The tree rooted at a node, with the id of every node in it.
+    fn hang_with_ids(&self, root: NodeId) -> (IndexedTree<T>, Vec<Option<NodeId>>) {
+        let mut tree = IndexedTree::new();
+        let mut ids = vec![None; self.len()];
+        self.hang_into(&mut tree, &mut ids, root, None, None, None);
+
+        (tree, ids)
+    }
+
+    pub fn root_at_node(&self, root: NodeId) -> IndexedTree<T> {
+        self.hang_with_ids(root).0
+    }
+
+    // Roots the tree on the edge between `u` and `v`, at distance `from_u` from `u` when the
+    // edge has a length. The subtree of `u` comes first.
+    pub fn root_on_edge_at(&self, u: NodeId, v: NodeId, from_u: f64) -> IndexedTree<T> {
+        let length = self.edge_length(u, v);
+        let (lu, lv) = match length {
+            Some(l) => (Some(from_u), Some(l - from_u)),
+            None    => (None, None)
+        };
+
+        let mut tree = IndexedTree::new();
+        let mut ids = vec![None; self.len()];
+        let root = tree.add_node(None, None);
+
+        self.hang_into(&mut tree, &mut ids, u, Some(v), Some(root), lu);
+        self.hang_into(&mut tree, &mut ids, v, Some(u), Some(root), lv);
+
+        tree
+    }
+
+    // Roots the tree at the middle of the edge between `u` and `v`.
+    pub fn root_on_edge(&self, u: NodeId, v: NodeId) -> IndexedTree<T> {
+        let half = self.edge_length(u, v).unwrap_or(0.0) / 2.0;
+        self.root_on_edge_at(u, v, half)
+    }
+
+    // Roots the tree on the edge of a leaf, with the leaf as the first child of the root.
+    pub fn root_at_leaf(&self, x: &T) -> Option<IndexedTree<T>>
+        where T: PartialEq {
+
+        let leaf = self.find_leaf(x)?;
+        let nb = self.neighbours(leaf).next()?;
+
+        Some(self.root_on_edge(leaf, nb))
+    }
+
+    // Roots the tree on the edge separating the outgroup from the other leaves, if there is
+    // one.
+    pub fn root_with_outgroup(&self, outgroup: &[T]) -> Option<IndexedTree<T>>
+        where T: Hash + Eq {
+
+        let outgroup: HashSet<&T> = outgroup.iter().collect();
+
+        let ingroup_leaf = self.leaves().find(|&id| self.label(id).is_some_and(|x| !outgroup.contains(x)))?;
+        let (hung, ids) = self.hang_with_ids(ingroup_leaf);
+
+        let mut origin = vec![NodeId::from_index(0); hung.len()];
+        for (u, id) in self.node_ids().zip(&ids) {
+            origin[id.unwrap().index()] = u;
+        }
+
+        // With the tree hung from an ingroup leaf, the outgroup is one side of an edge iff it
+        // is the cluster of some node.
+        let mut outgroup_leaves = vec![0; hung.len()];
+        let mut leaves = vec![0; hung.len()];
+
+        for id in hung.post_order() {
+            if hung.is_leaf(id) {
+                leaves[id.index()] = 1;
+
+                if hung.label(id).is_some_and(|x| outgroup.contains(x)) {
+                    outgroup_leaves[id.index()] = 1;
+                }
+            }
+
+            if let Some(p) = hung.parent(id) {
+                leaves[p.index()] += leaves[id.index()];
+                outgroup_leaves[p.index()] += outgroup_leaves[id.index()];
+            }
+        }
+
+        let found = outgroup_leaves[hung.root().index()];
+        if found == 0 || found < outgroup.len() {
+            return None;
+        }
+
+        let id = hung.node_ids().filter(|&id| !hung.is_root(id)).find(|&id| outgroup_leaves[id.index()] == found && leaves[id.index()] == found)?;
+        let p = hung.parent(id)?;
+
+        Some(self.root_on_edge(origin[id.index()], origin[p.index()]))
+    }
+
+    // Distances from `start` to every node, with the neighbour towards `start`.
+    fn distances_from(&self, start: NodeId) -> Vec<(f64, Option<NodeId>)> {
+        let mut dists = vec![(0.0, None); self.len()];
+        let mut stack = vec![(start, None)];
+
+        while let Some((u, from)) = stack.pop() {
+            for &(v, l) in &self.adjacency[u.index()] {
+                if Some(v) != from {
+                    dists[v.index()] = (dists[u.index()].0 + l.unwrap_or(1.0), Some(u));
+                    stack.push((v, Some(u)));
+                }
+            }
+        }
+
+        dists
+    }
+
+    // Roots the tree at the middle of the longest path between two leaves. Edges without a
+    // length count as one.
+    pub fn midpoint_root(&self) -> IndexedTree<T> {
+        let start = self.leaves().next().unwrap();
+
+        let farthest = |dists: &[(f64, Option<NodeId>)]| {
+            self.leaves().max_by(|a, b| dists[a.index()].0.partial_cmp(&dists[b.index()].0).unwrap()).unwrap()
+        };
+
+        let a = farthest(&self.distances_from(start));
+        let dists = self.distances_from(a);
+        let b = farthest(&dists);
+
+        let half = dists[b.index()].0 / 2.0;
+
+        // Walk back from b towards a until passing the middle.
+        let mut u = b;
+        while let Some(v) = dists[u.index()].1 {
+            if dists[v.index()].0 <= half {
+                return self.root_on_edge_at(v, u, half - dists[v.index()].0);
+            }
+
+            u = v;
+        }
+
+        self.root_at_node(a)
+    }
+
+    pub fn to_indexed(&self) -> IndexedTree<T> {
+        match self.neighbours(self.anchor).next() {
+            Some(nb) if self.is_leaf(self.anchor) => self.root_on_edge(nb, self.anchor),
+            _                                     => self.root_at_node(self.anchor)
+        }
+    }
+
+    pub fn to_phylo(&self) -> Phylo<T> {
+        self.to_indexed().to_phylo()
+    }
+}
+
+impl<'a, T: Clone> From<&'a Phylo<T>> for UnrootedTree<T> {
+    fn from(t: &'a Phylo<T>) -> Self {
+        UnrootedTree::from_rooted(&t.to_indexed())
+    }
+}
+
+impl<T: Clone + ToNewickLeaf> ToNewick for UnrootedTree<T> {
+    fn fmt_newick(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.to_phylo().fmt_newick(f)
+    }
+}
+
+
+impl<T: Clone> IndexedTree<T> {
+    pub fn unroot(&self) -> UnrootedTree<T> {
+        UnrootedTree::from_rooted(self)
+    }
+
+    // Collapses a root with two children, so that the tree hangs from a multifurcation as
+    // unrooted trees are usually written.
+    pub fn deroot(&self) -> IndexedTree<T> {
+        self.unroot().to_indexed()
+    }
+}
+
+impl<T: Clone> Phylo<T> {
+    pub fn unroot(&self) -> UnrootedTree<T> {
+        UnrootedTree::from(self)
+    }
+
+    pub fn deroot(&self) -> Self {
+        self.unroot().to_phylo()
+    }
+
+    pub fn reroot_at_leaf(&self, x: &T) -> Option<Self>
+        where T: PartialEq {
+
+        self.unroot().root_at_leaf(x).map(|t| t.to_phylo())
+    }
+
+    pub fn root_with_outgroup(&self, outgroup: &[T]) -> Option<Self>
+        where T: Hash + Eq {
+
+        self.unroot().root_with_outgroup(outgroup).map(|t| t.to_phylo())
+    }
+
+    pub fn midpoint_root(&self) -> Self {
+        self.unroot().midpoint_root().to_phylo()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    use phylo::{IndexedTree, LabelledTrees, NodeId, Phylo};
+    use super::UnrootedTree;
+
+    fn leaf(x: char) -> Phylo<char> {
+        Phylo::leaf_with(x)
+    }
+
+    fn node(ts: Vec<Phylo<char>>) -> Phylo<char> {
+        Phylo::node(ts)
+    }
+
+    // ((a, b), (c, (d, e)))
+    fn example() -> Phylo<char> {
+        node(vec![node(vec![leaf('a'), leaf('b')]), node(vec![leaf('c'), node(vec![leaf('d'), leaf('e')])])])
+    }
+
+    fn leaf_distance(t: &IndexedTree<char>, x: char) -> f64 {
+        t.root_distances()[t.find_leaf(&x).unwrap().index()]
+    }
+
+    #[test]
+    fn forgetting_the_root() {
+        let mut t = example().to_indexed();
+        t.set_length(NodeId::from_index(1), Some(1.0));
+        t.set_length(NodeId::from_index(4), Some(2.0));
+
+        let u = t.unroot();
+        assert_eq!(u.len(), 8);
+        assert_eq!(u.edges().len(), 7);
+        assert_eq!(u.leaves().count(), 5);
+        assert_eq!(u.node_ids().map(|id| u.degree(id)).max(), Some(3));
+
+        // The edges to the children of the root are joined.
+        let (ab, cde) = (NodeId::from_index(0), NodeId::from_index(3));
+        assert_eq!(u.edge_length(ab, cde), Some(3.0));
+
+        // A root with three children is kept.
+        let star = node(vec![leaf('a'), leaf('b'), leaf('c')]);
+        assert_eq!(star.unroot().len(), 4);
+        assert_eq!(star.deroot(), star);
+    }
+
+    #[test]
+    fn small_trees_round_trip() {
+        assert_eq!(leaf('a').deroot(), leaf('a'));
+        assert_eq!(leaf('a').midpoint_root(), leaf('a'));
+        assert_eq!(leaf('a').reroot_at_leaf(&'a'), None);
+
+        let cherry = node(vec![leaf('a'), leaf('b')]);
+        assert_eq!(cherry.unroot().len(), 2);
+        assert_eq!(cherry.deroot(), cherry);
+        assert_eq!(cherry.reroot_at_leaf(&'b'), Some(node(vec![leaf('b'), leaf('a')])));
+    }
+
+    #[test]
+    fn rerooting_keeps_the_unrooted_tree() {
+        let mut rng = StdRng::seed_from_u64(47);
+        let labels: Vec<u32> = (0..8).collect();
+        let trees = LabelledTrees::all(&labels);
+
+        for _ in 0..30 {
+            let t = trees.unrank(rng.random_range(0..trees.num_trees()));
+
+            let derooted = t.deroot();
+            assert_eq!(t.unrooted_rf_distance(&derooted), 0);
+            assert!(derooted.children().len() > 2);
+
+            for x in &labels {
+                let r = t.reroot_at_leaf(x).unwrap();
+
+                assert_eq!(r.children().len(), 2);
+                assert_eq!(r.children()[0], Phylo::leaf_with(*x));
+                assert_eq!(t.unrooted_rf_distance(&r), 0);
+            }
+
+            assert_eq!(t.unrooted_rf_distance(&t.midpoint_root()), 0);
+        }
+    }
+
+    #[test]
+    fn outgroup_rooting() {
+        let t = example();
+
+        let rooted = t.root_with_outgroup(&['d', 'e']).unwrap();
+        assert!(rooted.children().iter().any(|ch| ch == &node(vec![leaf('d'), leaf('e')])));
+        assert_eq!(t.unrooted_rf_distance(&rooted), 0);
+
+        let rooted = t.root_with_outgroup(&['a']).unwrap();
+        assert!(rooted.children().contains(&leaf('a')));
+
+        // The outgroup must be one side of an edge.
+        assert_eq!(t.root_with_outgroup(&['a', 'c']), None);
+        assert_eq!(t.root_with_outgroup(&['a', 'x']), None);
+        assert_eq!(t.root_with_outgroup(&[]), None);
+        assert_eq!(t.root_with_outgroup(&['a', 'b', 'c', 'd', 'e']), None);
+    }
+
+    #[test]
+    fn midpoint_rooting() {
+        // (a:1, b:1, c:4): the longest path, from a or b to c, has length 5.
+        let mut t = node(vec![leaf('a'), leaf('b'), leaf('c')]).to_indexed();
+        t.set_length(NodeId::from_index(1), Some(1.0));
+        t.set_length(NodeId::from_index(2), Some(1.0));
+        t.set_length(NodeId::from_index(3), Some(4.0));
+
+        let rooted = t.unroot().midpoint_root();
+        assert_eq!(rooted.num_children(rooted.root()), 2);
+        for &x in &['a', 'b', 'c'] {
+            assert_eq!(leaf_distance(&rooted, x), 2.5);
+        }
+
+        // Without lengths, every edge counts as one: the longest path, from a or b to e or
+        // f, has five edges, and the root goes on the third one.
+        let t = node(vec![
+            node(vec![leaf('a'), leaf('b')]),
+            node(vec![leaf('c'), node(vec![leaf('d'), node(vec![leaf('e'), leaf('f')])])])
+        ]);
+        let rooted = UnrootedTree::from(&t).midpoint_root();
+        let mut sides: Vec<Vec<char>> = rooted.to_phylo().children().iter()
+            .map(|ch| ch.get_leaves().into_iter().cloned().collect())
+            .collect();
+        sides.sort();
+
+        assert_eq!(sides, vec![vec!['a', 'b', 'c'], vec!['d', 'e', 'f']]);
+        assert_eq!(leaf_distance(&rooted, 'a'), leaf_distance(&rooted, 'e'));
+    }
+
+    #[test]
+    fn edge_rooting_splits_the_length() {
+        let mut t = node(vec![leaf('a'), leaf('b'), leaf('c')]).to_indexed();
+        t.set_length(NodeId::from_index(3), Some(4.0));
+
+        let u = t.unroot();
+        let (c, centre) = (u.find_leaf(&'c').unwrap(), NodeId::from_index(0));
+
+        let rooted = u.root_on_edge_at(c, centre, 1.0);
+        assert_eq!(leaf_distance(&rooted, 'c'), 1.0);
+        assert_eq!(leaf_distance(&rooted, 'a'), 4.0);
+
+        let rooted = u.root_on_edge(centre, c);
+        assert_eq!(leaf_distance(&rooted, 'c'), 2.0);
+        assert_eq!(rooted.label(rooted.children(rooted.root()).nth(1).unwrap()), Some(&'c'));
+    }
+}
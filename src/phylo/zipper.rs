@@ -0,0 +1,405 @@
+use std::mem;
+
+use phylo::Phylo;
+
+
+struct Crumb<T> {
+    parent: Phylo<T>,
+    siblings: Option<Vec<Phylo<T>>>,
+    index: usize,
+    parent_changed: bool
+}
+
+impl<T: Clone> Crumb<T> {
+    fn siblings_mut(&mut self) -> &mut Vec<Phylo<T>> {
+        let parent = &self.parent;
+        self.siblings.get_or_insert_with(|| parent.children().to_vec())
+    }
+
+    fn sibling(&self, i: usize) -> Option<&Phylo<T>> {
+        match self.siblings {
+            Some(ref ts) => ts.get(i),
+            None         => self.parent.children().get(i)
+        }
+    }
+
+    fn num_siblings(&self) -> usize {
+        match self.siblings {
+            Some(ref ts) => ts.len(),
+            None         => self.parent.children().len()
+        }
+    }
+}
+
+
+// A cursor into a persistent tree. Subtrees that are not edited are shared with the original
+// tree, and only the nodes on the path from an edit to the root are rebuilt.
+pub struct Zipper<T> {
+    focus: Phylo<T>,
+    changed: bool,
+    crumbs: Vec<Crumb<T>>
+}
+
+impl<T: Clone> Zipper<T> {
+    pub fn new(t: Phylo<T>) -> Self {
+        Zipper {
+            focus: t,
+            changed: false,
+            crumbs: Vec::new()
+        }
+    }
+
+    pub fn focus(&self) -> &Phylo<T> {
+        &self.focus
+    }
+
+    pub fn depth(&self) -> usize {
+        self.crumbs.len()
+    }
+
+    pub fn path(&self) -> Vec<usize> {
+        self.crumbs.iter().map(|c| c.index).collect()
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.crumbs.is_empty()
+    }
+
+    pub fn index(&self) -> Option<usize> {
+        self.crumbs.last().map(|c| c.index)
+    }
+
+    pub fn down(&mut self, i: usize) -> bool {
+        let child = match self.focus.children().get(i) {
+            Some(ch) => ch.clone(),
+            None     => return false
+        };
+
+        let parent = mem::replace(&mut self.focus, child);
+
+        self.crumbs.push(Crumb {
+            parent,
+            siblings: None,
+            index: i,
+            parent_changed: self.changed
+        });
+        self.changed = false;
+
+        true
+    }
+
+    pub fn up(&mut self) -> bool {
+        let crumb = match self.crumbs.pop() {
+            Some(c) => c,
+            None    => return false
+        };
+
+        let Crumb { parent, siblings, index, parent_changed } = crumb;
+
+        if self.changed || siblings.is_some() {
+            let mut ts = siblings.unwrap_or_else(|| parent.children().to_vec());
+
+            if self.changed {
+                ts[index] = mem::replace(&mut self.focus, parent);
+            }
+
+            self.focus = Phylo::node(ts);
+            self.changed = true;
+        } else {
+            self.focus = parent;
+            self.changed = parent_changed;
+        }
+
+        true
+    }
+
+    pub fn follow(&mut self, path: &[usize]) -> bool {
+        let depth = self.depth();
+
+        for &i in path {
+            if !self.down(i) {
+                while self.depth() > depth {
+                    self.up();
+                }
+
+                return false;
+            }
+        }
+
+        true
+    }
+
+    pub fn to_root(&mut self) {
+        while self.up() {}
+    }
+
+    fn move_to_sibling(&mut self, j: usize) -> bool {
+        let changed = self.changed;
+
+        let sibling = match self.crumbs.last() {
+            Some(c) => match c.sibling(j) {
+                Some(s) => s.clone(),
+                None    => return false
+            },
+            None => return false
+        };
+
+        let focus = mem::replace(&mut self.focus, sibling);
+        let crumb = self.crumbs.last_mut().unwrap();
+
+        if changed {
+            let i = crumb.index;
+            crumb.siblings_mut()[i] = focus;
+        }
+
+        crumb.index = j;
+        self.changed = false;
+
+        true
+    }
+
+    pub fn left(&mut self) -> bool {
+        match self.index() {
+            Some(i) if i > 0 => self.move_to_sibling(i - 1),
+            _                => false
+        }
+    }
+
+    pub fn right(&mut self) -> bool {
+        match self.index() {
+            Some(i) => self.move_to_sibling(i + 1),
+            None    => false
+        }
+    }
+
+    pub fn replace(&mut self, t: Phylo<T>) -> Phylo<T> {
+        self.changed = true;
+        mem::replace(&mut self.focus, t)
+    }
+
+    pub fn modify<F>(&mut self, f: F)
+        where F: FnOnce(&Phylo<T>) -> Phylo<T> {
+
+        let t = f(&self.focus);
+        self.replace(t);
+    }
+
+    pub fn insert_child(&mut self, i: usize, t: Phylo<T>) -> bool {
+        if self.focus.is_leaf() || i > self.focus.children().len() {
+            return false;
+        }
+
+        let mut ts = self.focus.children().to_vec();
+        ts.insert(i, t);
+
+        self.replace(Phylo::node(ts));
+        true
+    }
+
+    pub fn insert_left(&mut self, t: Phylo<T>) -> bool {
+        match self.crumbs.last_mut() {
+            None    => false,
+            Some(c) => {
+                let i = c.index;
+                c.siblings_mut().insert(i, t);
+                c.index += 1;
+                true
+            }
+        }
+    }
+
+    pub fn insert_right(&mut self, t: Phylo<T>) -> bool {
+        match self.crumbs.last_mut() {
+            None    => false,
+            Some(c) => {
+                let i = c.index;
+                c.siblings_mut().insert(i + 1, t);
+                true
+            }
+        }
+    }
+
+    // Removes the focused subtree and moves the focus to its parent. A node's only child
+    // cannot be removed.
+    pub fn remove(&mut self) -> Option<Phylo<T>> {
+        if self.crumbs.last().is_none_or(|c| c.num_siblings() < 2) {
+            return None;
+        }
+
+        let Crumb { parent, siblings, index, .. } = self.crumbs.pop().unwrap();
+
+        let mut ts = siblings.unwrap_or_else(|| parent.children().to_vec());
+        ts.remove(index);
+
+        let removed = mem::replace(&mut self.focus, Phylo::node(ts));
+        self.changed = true;
+
+        Some(removed)
+    }
+
+    pub fn into_root(mut self) -> Phylo<T> {
+        self.to_root();
+        self.focus
+    }
+
+    pub fn into_focus(self) -> Phylo<T> {
+        self.focus
+    }
+}
+
+impl<T: Clone> Phylo<T> {
+    pub fn zipper(&self) -> Zipper<T> {
+        Zipper::new(self.clone())
+    }
+
+    pub fn zipper_at(&self, path: &[usize]) -> Option<Zipper<T>> {
+        let mut z = self.zipper();
+
+        if z.follow(path) {
+            Some(z)
+        } else {
+            None
+        }
+    }
+
+    pub fn replace_at(&self, path: &[usize], t: Self) -> Option<Self> {
+        let mut z = self.zipper_at(path)?;
+        z.replace(t);
+        Some(z.into_root())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use phylo::Phylo;
+
+    fn leaf(x: u32) -> Phylo<u32> {
+        Phylo::leaf_with(x)
+    }
+
+    // ((0, 1, 2), (3, 4))
+    fn example() -> Phylo<u32> {
+        Phylo::node(vec![
+            Phylo::node(vec![leaf(0), leaf(1), leaf(2)]),
+            Phylo::node(vec![leaf(3), leaf(4)])
+        ])
+    }
+
+    #[test]
+    fn navigation() {
+        let t = example();
+        let mut z = t.zipper();
+
+        assert!(z.is_root());
+        assert!(!z.up() && !z.left() && !z.right());
+
+        assert!(z.down(0) && z.down(1));
+        assert_eq!(z.focus(), &leaf(1));
+        assert_eq!(z.path(), vec![0, 1]);
+        assert_eq!(z.depth(), 2);
+
+        assert!(z.right());
+        assert_eq!(z.focus(), &leaf(2));
+        assert_eq!(z.index(), Some(2));
+
+        // The last child has no right sibling, and the focus stays on it.
+        assert!(!z.right());
+        assert_eq!(z.focus(), &leaf(2));
+        assert_eq!(z.path(), vec![0, 2]);
+
+        assert!(z.left() && z.left());
+        assert!(!z.left());
+        assert_eq!(z.focus(), &leaf(0));
+
+        assert!(!z.down(0));
+        assert!(z.up() && z.up());
+        assert_eq!(z.focus(), &t);
+        assert!(!z.down(2));
+    }
+
+    #[test]
+    fn follow_fails_without_moving() {
+        let t = example();
+        let mut z = t.zipper();
+
+        assert!(z.follow(&[1]));
+        assert!(!z.follow(&[0, 0]));
+        assert_eq!(z.path(), vec![1]);
+
+        assert!(t.zipper_at(&[1, 1]).is_some());
+        assert!(t.zipper_at(&[1, 2]).is_none());
+        assert!(t.zipper_at(&[]).unwrap().is_root());
+    }
+
+    #[test]
+    fn edits_rebuild_only_the_path_to_the_root() {
+        let t = example();
+        let edited = t.replace_at(&[0, 1], leaf(9)).unwrap();
+
+        assert_eq!(edited, Phylo::node(vec![
+            Phylo::node(vec![leaf(0), leaf(9), leaf(2)]),
+            Phylo::node(vec![leaf(3), leaf(4)])
+        ]));
+        assert_eq!(t, example());
+
+        // The subtree off the path is shared with the original tree.
+        let (before, after) = (&t.children()[1], &edited.children()[1]);
+        assert!(::std::ptr::eq(before.children().as_ptr(), after.children().as_ptr()));
+
+        assert_eq!(t.replace_at(&[2], leaf(9)), None);
+    }
+
+    #[test]
+    fn edits_survive_moves_to_siblings() {
+        let mut z = example().zipper();
+
+        assert!(z.follow(&[0, 0]));
+        z.replace(leaf(7));
+        assert!(z.right() && z.right());
+        z.modify(|t| Phylo::node(vec![t.clone(), leaf(8)]));
+        assert!(z.up() && z.right());
+        z.replace(leaf(6));
+
+        assert_eq!(z.into_root(), Phylo::node(vec![
+            Phylo::node(vec![leaf(7), leaf(1), Phylo::node(vec![leaf(2), leaf(8)])]),
+            leaf(6)
+        ]));
+    }
+
+    #[test]
+    fn insertions() {
+        let mut z = example().zipper();
+
+        assert!(!z.insert_left(leaf(5)) && !z.insert_right(leaf(5)));
+        assert!(z.down(1));
+        assert!(z.insert_child(2, leaf(5)));
+        assert!(!z.insert_child(4, leaf(6)));
+        assert!(z.down(0));
+        assert!(!z.insert_child(0, leaf(6)));
+        assert!(z.insert_left(leaf(6)) && z.insert_right(leaf(7)));
+        assert_eq!(z.index(), Some(1));
+
+        assert_eq!(z.into_root(), Phylo::node(vec![
+            Phylo::node(vec![leaf(0), leaf(1), leaf(2)]),
+            Phylo::node(vec![leaf(6), leaf(3), leaf(7), leaf(4), leaf(5)])
+        ]));
+    }
+
+    #[test]
+    fn removal() {
+        let t = Phylo::node(vec![Phylo::node(vec![leaf(0)]), leaf(1), leaf(2)]);
+        let mut z = t.zipper();
+
+        assert_eq!(z.remove(), None);
+
+        // An only child cannot be removed.
+        assert!(z.follow(&[0, 0]));
+        assert_eq!(z.remove(), None);
+
+        assert!(z.up() && z.right());
+        assert_eq!(z.remove(), Some(leaf(1)));
+        assert!(z.is_root());
+        assert_eq!(z.into_focus(), Phylo::node(vec![Phylo::node(vec![leaf(0)]), leaf(2)]));
+    }
+}
@@ -2,7 +2,9 @@ use lazy_static::lazy_static;
 
 use phylo::{Phylo, Leaf, Node};
 use shape::{CHERRY, Shape};
-use util::{binom2, factorial};
+use shape::intern::{ShapeId, ShapeInterner};
+use shape::memo::ShapeMemo;
+use util::{binom2, checked_factorial};
 
 
 lazy_static! {
@@ -36,36 +38,36 @@ impl<T: Eq> Phylo<T> {
         self.fold(0u32, |t, it| t.is_symmetric() as u32 + it.sum::<u32>())
     }
 
-    pub fn count_automorphisms(&self) -> u32 {
-        match self {
-            Leaf(..) => 1,
-
-            Node(ref ts) => {
-                let mut aut: u32 = 1;
-                let mut cur_sym_class_rep: Option<&Self> = None;
-                let mut cur_sym_class_aut: u32 = 1;
-                let mut cur_sym_class_len: u32 = 1;
-
-                fn compute_aut_factor(class_aut: u32, class_len: u32) -> u32 {
-                    class_aut.pow(class_len) * factorial(class_len)
-                }
-
-                for ti in ts.iter() {
-                    if cur_sym_class_rep.is_none() || !cur_sym_class_rep.unwrap().isomorphic(ti) {
-                        aut *= compute_aut_factor(cur_sym_class_aut, cur_sym_class_len);
+    pub fn count_automorphisms(&self) -> u128 {
+        self.symmetry_classes().iter()
+            .try_fold(1u128, |aut, &(k, _)| checked_factorial(k).and_then(|f| aut.checked_mul(f)))
+            .expect("too many automorphisms to count")
+    }
+}
 
-                        cur_sym_class_rep = Some(ti);
-                        cur_sym_class_aut = ti.count_automorphisms();
-                        cur_sym_class_len = 1;
-                    } else {
-                        cur_sym_class_len += 1;
-                    }
+impl<T> Phylo<T> {
+    // The numbers of isomorphic children of every internal node, class by class, and whether
+    // they are leaves. Automorphisms permute the subtrees of every class, so they are counted
+    // by the product of the factorials of these numbers.
+    pub(crate) fn symmetry_classes(&self) -> Vec<(usize, bool)> {
+        let mut classes = Vec::new();
+
+        for v in self.pre_order() {
+            let mut chs: Vec<&Self> = v.node.children().iter().collect();
+            chs.sort_by(|a, b| a.cmp_shape_grlex(b));
+
+            let mut len = 1;
+            for i in 1..chs.len() + 1 {
+                if i < chs.len() && chs[i].isomorphic(chs[i-1]) {
+                    len += 1;
+                } else {
+                    classes.push((len, chs[i-1].is_leaf()));
+                    len = 1;
                 }
-
-                aut *= compute_aut_factor(cur_sym_class_aut, cur_sym_class_len);
-                aut
             }
         }
+
+        classes
     }
 }
 
@@ -135,9 +137,80 @@ impl<T> Phylo<T> {
     pub fn quartet_index(&self, quartet_values: Option<&[u32; 5]>) -> u32 {
         let vs = quartet_values.unwrap_or(&[0,1,2,3,4]);
 
-        let r = self.fold(QI::leaf(), |t, it| QI::from_rec(t, &it.collect::<Vec<_>>(), vs));
+        let r = self.fold(QI::leaf(), |t, it| QI::from_rec(|s| t.isomorphic(s), it.as_slice(), vs));
+
+        r.quartets
+    }
+}
+
+
+pub struct AutomorphismsMemo {
+    memo: ShapeMemo<u128>
+}
+
+impl Default for AutomorphismsMemo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AutomorphismsMemo {
+    pub fn new() -> Self {
+        AutomorphismsMemo {
+            memo: ShapeMemo::new()
+        }
+    }
+
+    pub fn count_automorphisms(&mut self, interner: &ShapeInterner, id: ShapeId) -> u128 {
+        self.memo.eval(interner, id, |interner, id, auts| {
+            let chs = interner.children(id);
+            let times = |aut: Option<u128>, class_len| {
+                aut?.checked_mul(checked_factorial(class_len)?)
+            };
+
+            let mut aut = auts.iter().try_fold(1u128, |aut, &a| aut.checked_mul(a));
+            let mut class_len = 1;
+
+            for i in 1..chs.len() {
+                if chs[i] == chs[i-1] {
+                    class_len += 1;
+                } else {
+                    aut = times(aut, class_len);
+                    class_len = 1;
+                }
+            }
 
-        return r.quartets;
+            times(aut, class_len).expect("too many automorphisms to count")
+        })
+    }
+}
+
+
+pub struct QuartetIndexMemo {
+    memo: ShapeMemo<QI>,
+    quartet_values: [u32; 5]
+}
+
+impl QuartetIndexMemo {
+    pub fn new(quartet_values: Option<&[u32; 5]>) -> Self {
+        QuartetIndexMemo {
+            memo: ShapeMemo::new(),
+            quartet_values: *quartet_values.unwrap_or(&[0,1,2,3,4])
+        }
+    }
+
+    pub fn quartet_index(&mut self, interner: &ShapeInterner, id: ShapeId) -> u32 {
+        let vs = &self.quartet_values;
+
+        let r = self.memo.eval(interner, id, |interner, id, qi_rec| {
+            if interner.is_leaf(id) {
+                QI::leaf()
+            } else {
+                QI::from_rec(|s| interner.get(s) == Some(id), qi_rec, vs)
+            }
+        });
+
+        r.quartets
     }
 }
 
@@ -158,26 +231,30 @@ impl QI {
         }
     }
 
-    fn from_rec<T>(t: &Phylo<T>, qi_rec: &[QI], quartet_values: &[u32; 5]) -> QI {
+    fn from_rec<F>(is: F, qi_rec: &[QI], quartet_values: &[u32; 5]) -> QI
+        where F: Fn(&Shape) -> bool {
+
         let k = qi_rec.len();
 
         let kappa = qi_rec.iter().map(|s| s.kappa).sum();
 
-        let triplets = QI::compute_triplets(t, k, kappa, &qi_rec);
+        let triplets = QI::compute_triplets(&is, k, kappa, qi_rec);
 
-        let quartets = QI::compute_quartets(t, k, kappa, &qi_rec, quartet_values);
+        let quartets = QI::compute_quartets(&is, k, kappa, qi_rec, quartet_values);
 
         QI {
-            quartets: quartets,
-            triplets: triplets,
-            kappa: kappa
+            quartets,
+            triplets,
+            kappa
         }
     }
 
-    fn compute_triplets<T>(t: &Phylo<T>, k: usize, kappa: u32, qi_rec: &[QI]) -> u32 {
+    fn compute_triplets<F>(is: &F, k: usize, kappa: u32, qi_rec: &[QI]) -> u32
+        where F: Fn(&Shape) -> bool {
+
         if kappa < 3 {
             0
-        } else if t.isomorphic(&TRIPLET) {
+        } else if is(&TRIPLET) {
             1
         } else {
             let t_s0: u32 = qi_rec.iter().map(|s| s.triplets).sum();
@@ -190,10 +267,12 @@ impl QI {
         }
     }
 
-    fn compute_quartets<T>(t: &Phylo<T>, k: usize, kappa: u32, qi_rec: &[QI], quartet_values: &[u32; 5]) -> u32 {
+    fn compute_quartets<F>(is: &F, k: usize, kappa: u32, qi_rec: &[QI], quartet_values: &[u32; 5]) -> u32
+        where F: Fn(&Shape) -> bool {
+
         match kappa {
             n if n < 4 => 0,
-            4 => *QUARTETS.iter().zip(quartet_values).find(|(q, _)| t.isomorphic(q)).unwrap().1,
+            4 => *QUARTETS.iter().zip(quartet_values).find(|(q, _)| is(q)).unwrap().1,
             _ => {
                 let s0: u32 = qi_rec.iter().map(|s| s.quartets).sum();
 
@@ -221,3 +300,59 @@ impl QI {
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use phylo::Phylo;
+    use shape::generator::all_tree_ids;
+    use shape::intern::ShapeInterner;
+    use super::AutomorphismsMemo;
+
+    fn star(n: usize) -> Phylo<()> {
+        Phylo::node(vec![Phylo::leaf_with(()); n])
+    }
+
+    #[test]
+    fn automorphisms_of_small_shapes() {
+        let leaf = Phylo::leaf_with(());
+        let cherry = star(2);
+
+        // The classes do not need to be next to each other.
+        assert_eq!(Phylo::node(vec![leaf.clone(), cherry.clone(), leaf.clone()]).count_automorphisms(), 4);
+        assert_eq!(Phylo::node(vec![cherry.clone(), cherry.clone()]).count_automorphisms(), 8);
+        assert_eq!(Phylo::node(vec![cherry, star(3), leaf]).count_automorphisms(), 12);
+    }
+
+    #[test]
+    fn automorphisms_of_large_stars_do_not_overflow() {
+        let mut interner = ShapeInterner::new();
+        let mut memo = AutomorphismsMemo::new();
+        let factorial_13 = (1..14).product::<u128>();
+
+        assert_eq!(star(13).count_automorphisms(), factorial_13);
+
+        let id = interner.intern(&star(13));
+        assert_eq!(memo.count_automorphisms(&interner, id), factorial_13);
+        assert_eq!(star(34).count_automorphisms(), (1..35).product::<u128>());
+    }
+
+    #[test]
+    #[should_panic(expected = "too many automorphisms to count")]
+    fn automorphisms_that_do_not_fit_panic() {
+        star(35).count_automorphisms();
+    }
+
+    #[test]
+    fn memoised_automorphisms_match() {
+        let mut interner = ShapeInterner::new();
+        let mut memo = AutomorphismsMemo::new();
+
+        for n in 1..9 {
+            for id in all_tree_ids(&mut interner, n) {
+                let t = interner.to_shape(id);
+                assert_eq!(memo.count_automorphisms(&interner, id), t.count_automorphisms());
+            }
+        }
+    }
+}
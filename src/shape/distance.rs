@@ -0,0 +1,333 @@
+use std::collections::{HashMap, HashSet};
+
+use phylo::Phylo;
+use shape::intern::{ShapeId, ShapeInterner};
+use util::min_cost_assignment;
+
+
+// Subshapes of `id`, including itself, in increasing id order (children before parents).
+fn subshapes(interner: &ShapeInterner, id: ShapeId) -> Vec<ShapeId> {
+    let mut seen = HashSet::new();
+    let mut stack = vec![id];
+    seen.insert(id);
+
+    while let Some(i) = stack.pop() {
+        for &ch in interner.children(i) {
+            if seen.insert(ch) {
+                stack.push(ch);
+            }
+        }
+    }
+
+    let mut ids: Vec<ShapeId> = seen.into_iter().collect();
+    ids.sort();
+    ids
+}
+
+// Number of subtrees (one per node) of `id` isomorphic to each shape.
+pub fn subtree_spectrum(interner: &ShapeInterner, id: ShapeId) -> HashMap<ShapeId, u32> {
+    let mut counts = HashMap::new();
+    counts.insert(id, 1);
+
+    for i in subshapes(interner, id).into_iter().rev() {
+        let c = counts[&i];
+
+        for &ch in interner.children(i) {
+            *counts.entry(ch).or_insert(0) += c;
+        }
+    }
+
+    counts
+}
+
+fn lp_norm<I>(diffs: I, p: f64) -> f64
+    where I: Iterator<Item=f64> {
+
+    assert!(p >= 1.0, "p must be at least 1");
+
+    if p.is_infinite() {
+        diffs.fold(0.0, f64::max)
+    } else {
+        diffs.map(|d| d.powf(p)).sum::<f64>().powf(1.0 / p)
+    }
+}
+
+// L^p distance between the subtree spectra of two shapes.
+pub fn spectrum_distance(interner: &ShapeInterner, a: ShapeId, b: ShapeId, p: f64) -> f64 {
+    let sa = subtree_spectrum(interner, a);
+    let sb = subtree_spectrum(interner, b);
+
+    let diffs = sa.iter()
+        .map(|(id, &ca)| (ca as f64 - sb.get(id).cloned().unwrap_or(0) as f64).abs())
+        .chain(sb.iter()
+            .filter(|&(id, _)| !sa.contains_key(id))
+            .map(|(_, &cb)| cb as f64));
+
+    lp_norm(diffs, p)
+}
+
+
+// Colijn and Plazzotta (2018): leaves are labelled 1 and a cherry of subtrees labelled
+// k >= j is labelled k(k-1)/2 + j + 1, which numbers the binary shapes bijectively. None if
+// the shape is not binary or its label overflows.
+pub fn colijn_plazzotta_label(interner: &ShapeInterner, id: ShapeId) -> Option<u128> {
+    let mut labels: HashMap<ShapeId, u128> = HashMap::new();
+
+    for i in subshapes(interner, id) {
+        let label = match *interner.children(i) {
+            []         => 1,
+            [c1, c2]   => {
+                let (l1, l2) = (labels[&c1], labels[&c2]);
+                let (k, j) = (l1.max(l2), l1.min(l2));
+
+                (k.checked_mul(k - 1)? / 2).checked_add(j + 1)?
+            },
+            _          => return None
+        };
+
+        labels.insert(i, label);
+    }
+
+    labels.remove(&id)
+}
+
+// Euclidean distance between the vectors counting the subtrees with each Colijn-Plazzotta
+// label. Since labels number the binary shapes bijectively, this is the L^2 spectrum distance.
+pub fn colijn_plazzotta_distance(interner: &ShapeInterner, a: ShapeId, b: ShapeId) -> f64 {
+    let is_binary = |id| subshapes(interner, id).into_iter()
+        .all(|i| interner.is_leaf(i) || interner.children(i).len() == 2);
+
+    assert!(is_binary(a) && is_binary(b), "Colijn-Plazzotta labels need binary shapes");
+
+    spectrum_distance(interner, a, b, 2.0)
+}
+
+
+// Zhang's (1996) constrained edit distance between unordered shapes, where inserting or
+// deleting a node costs one and disjoint subtrees must be mapped to disjoint subtrees. Keeps
+// the tree and forest distances of every pair of subshapes already compared.
+pub struct EditDistanceMemo {
+    sizes: HashMap<ShapeId, u32>,
+    values: HashMap<(ShapeId, ShapeId), (u32, u32)>
+}
+
+impl Default for EditDistanceMemo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EditDistanceMemo {
+    pub fn new() -> Self {
+        EditDistanceMemo {
+            sizes: HashMap::new(),
+            values: HashMap::new()
+        }
+    }
+
+    fn size(&mut self, interner: &ShapeInterner, id: ShapeId) -> u32 {
+        for i in subshapes(interner, id) {
+            if !self.sizes.contains_key(&i) {
+                let size = 1 + interner.children(i).iter().map(|ch| self.sizes[ch]).sum::<u32>();
+                self.sizes.insert(i, size);
+            }
+        }
+
+        self.sizes[&id]
+    }
+
+    fn forest_matching(&self, interner: &ShapeInterner, x: ShapeId, y: ShapeId) -> u32 {
+        let (xs, ys) = (interner.children(x), interner.children(y));
+        let (rows, cols, swap) = if xs.len() <= ys.len() { (xs, ys, false) } else { (ys, xs, true) };
+
+        let unmatched: u32 = rows.iter().chain(cols).map(|i| self.sizes[i]).sum();
+
+        // Matching two subtrees only pays off when it is cheaper than deleting and inserting
+        // them, so the savings are clamped at zero.
+        let cost: Vec<Vec<i64>> = rows.iter()
+            .map(|&r| cols.iter()
+                .map(|&c| {
+                    let (s, t) = if swap { (c, r) } else { (r, c) };
+                    let d = self.values[&(s, t)].0 as i64;
+
+                    (d - self.sizes[&s] as i64 - self.sizes[&t] as i64).min(0)
+                })
+                .collect())
+            .collect();
+
+        let (saved, _) = min_cost_assignment(&cost);
+        (unmatched as i64 + saved) as u32
+    }
+
+    fn compare(&mut self, interner: &ShapeInterner, x: ShapeId, y: ShapeId) -> (u32, u32) {
+        let (sx, sy) = (self.sizes[&x], self.sizes[&y]);
+        let (xs, ys) = (interner.children(x), interner.children(y));
+
+        let forest = if xs.is_empty() || ys.is_empty() {
+            sx + sy - 2
+        } else {
+            let insert = ys.iter()
+                .map(|&t| self.values[&(x, t)].1 as i64 - (self.sizes[&t] - 1) as i64)
+                .min().unwrap() + (sy - 1) as i64;
+
+            let delete = xs.iter()
+                .map(|&s| self.values[&(s, y)].1 as i64 - (self.sizes[&s] - 1) as i64)
+                .min().unwrap() + (sx - 1) as i64;
+
+            (insert.min(delete) as u32).min(self.forest_matching(interner, x, y))
+        };
+
+        let mut tree = forest;
+
+        if let Some(d) = ys.iter().map(|&t| self.values[&(x, t)].0 + sy - self.sizes[&t]).min() {
+            tree = tree.min(d);
+        }
+
+        if let Some(d) = xs.iter().map(|&s| self.values[&(s, y)].0 + sx - self.sizes[&s]).min() {
+            tree = tree.min(d);
+        }
+
+        (tree, forest)
+    }
+
+    pub fn edit_distance(&mut self, interner: &ShapeInterner, a: ShapeId, b: ShapeId) -> u32 {
+        self.size(interner, a);
+        self.size(interner, b);
+
+        let (sa, sb) = (subshapes(interner, a), subshapes(interner, b));
+
+        for &x in &sa {
+            for &y in &sb {
+                if !self.values.contains_key(&(x, y)) {
+                    let d = self.compare(interner, x, y);
+                    self.values.insert((x, y), d);
+                }
+            }
+        }
+
+        self.values[&(a, b)].0
+    }
+}
+
+
+impl<T> Phylo<T> {
+    pub fn spectrum_distance<U>(&self, other: &Phylo<U>, p: f64) -> f64 {
+        let mut interner = ShapeInterner::new();
+        let (a, b) = (interner.intern(self), interner.intern(other));
+
+        spectrum_distance(&interner, a, b, p)
+    }
+
+    pub fn colijn_plazzotta_distance<U>(&self, other: &Phylo<U>) -> f64 {
+        let mut interner = ShapeInterner::new();
+        let (a, b) = (interner.intern(self), interner.intern(other));
+
+        colijn_plazzotta_distance(&interner, a, b)
+    }
+
+    pub fn edit_distance<U>(&self, other: &Phylo<U>) -> u32 {
+        let mut interner = ShapeInterner::new();
+        let (a, b) = (interner.intern(self), interner.intern(other));
+
+        EditDistanceMemo::new().edit_distance(&interner, a, b)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use shape::Shape;
+    use shape::generator::{all_binary_tree_ids, all_tree_ids};
+    use shape::intern::ShapeInterner;
+    use super::*;
+
+    fn label(t: &Shape) -> Option<u128> {
+        let mut interner = ShapeInterner::new();
+        let id = interner.intern(t);
+
+        colijn_plazzotta_label(&interner, id)
+    }
+
+    #[test]
+    fn colijn_plazzotta_labels_of_small_shapes() {
+        assert_eq!(label(&make_shape!(*)), Some(1));
+        assert_eq!(label(&make_shape!((*, *))), Some(2));
+        assert_eq!(label(&make_shape!(((*, *), *))), Some(3));
+        assert_eq!(label(&make_shape!(((*, *), (*, *)))), Some(4));
+        assert_eq!(label(&make_shape!((((*, *), *), *))), Some(5));
+        assert_eq!(label(&make_shape!((((*, *), *), ((*, *), *)))), Some(7));
+        assert_eq!(label(&make_shape!((*, *, *))), None);
+    }
+
+    #[test]
+    fn colijn_plazzotta_labels_are_distinct() {
+        let mut interner = ShapeInterner::new();
+        let mut seen = HashSet::new();
+
+        for n in 1..11 {
+            for id in all_binary_tree_ids(&mut interner, n) {
+                assert!(seen.insert(colijn_plazzotta_label(&interner, id).unwrap()));
+            }
+        }
+    }
+
+    #[test]
+    fn spectrum_distances() {
+        let cherry = make_shape!((*, *));
+        let caterpillar = make_shape!(((*, *), *));
+
+        // The spectra are {leaf: 2, cherry: 1} and {leaf: 3, cherry: 1, caterpillar: 1}.
+        assert_eq!(cherry.spectrum_distance(&caterpillar, 1.0), 2.0);
+        assert_eq!(cherry.spectrum_distance(&caterpillar, f64::INFINITY), 1.0);
+        assert_eq!(cherry.colijn_plazzotta_distance(&caterpillar), 2f64.sqrt());
+        assert_eq!(caterpillar.spectrum_distance(&caterpillar, 1.0), 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Colijn-Plazzotta labels need binary shapes")]
+    fn colijn_plazzotta_distances_of_multifurcating_shapes_panic() {
+        make_shape!((*, *)).colijn_plazzotta_distance(&make_shape!((*, *, *)));
+    }
+
+    #[test]
+    fn edit_distances_of_small_shapes() {
+        let leaf = make_shape!(*);
+        let cherry = make_shape!((*, *));
+
+        assert_eq!(leaf.edit_distance(&cherry), 2);
+        assert_eq!(cherry.edit_distance(&make_shape!((*, *, *))), 1);
+        assert_eq!(leaf.edit_distance(&make_shape!((*, *, *, *))), 4);
+        assert_eq!(cherry.edit_distance(&make_shape!(((*, *), *))), 2);
+
+        // Deleting the cherry's root would give its leaves the same lowest common ancestor as
+        // the third leaf, which a constrained mapping cannot do: a leaf is replaced instead.
+        assert_eq!(make_shape!(((*, *), *)).edit_distance(&make_shape!((*, *, *))), 3);
+    }
+
+    #[test]
+    fn edit_distances_are_metrics() {
+        let mut interner = ShapeInterner::new();
+        let ids: Vec<ShapeId> = (1..6).flat_map(|n| all_tree_ids(&mut interner, n)).collect();
+
+        let mut memo = EditDistanceMemo::new();
+        let sizes: Vec<u32> = ids.iter().map(|&id| memo.size(&interner, id)).collect();
+
+        for (i, &a) in ids.iter().enumerate() {
+            for (j, &b) in ids.iter().enumerate() {
+                let d = memo.edit_distance(&interner, a, b);
+
+                assert_eq!(d == 0, a == b);
+                assert_eq!(d, memo.edit_distance(&interner, b, a));
+                assert!(d >= (sizes[i] as i64 - sizes[j] as i64).unsigned_abs() as u32);
+                assert!(d <= sizes[i] + sizes[j] - 2);
+
+                for &c in &ids {
+                    let via = memo.edit_distance(&interner, a, c) + memo.edit_distance(&interner, c, b);
+                    assert!(d <= via);
+                }
+            }
+        }
+    }
+}
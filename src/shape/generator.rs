@@ -1,11 +1,14 @@
 use shape::{Shape, Leaf, Node};
-use std::collections::BTreeSet;
+use shape::intern::{ShapeId, ShapeInterner};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::iter;
 use std::sync::Arc;
 use rayon::prelude::*;
 
 use cached::cached;
 
+use util::count_multisets;
+
 
 pub fn iter_insert_tree<'r>(ts: &'r [Shape], t2: &'r Shape) -> impl Iterator<Item=&'r Shape> {
     let (before, after) = match ts.iter().enumerate().find(|i_t| i_t.1 >= t2) {
@@ -143,3 +146,101 @@ cached! {
         Arc::new(generate(n))
     }
 }
+
+
+fn generate_ids_from(interner: &mut ShapeInterner,
+                     memo: &mut HashMap<ShapeId, Vec<ShapeId>>,
+                     t: ShapeId,
+                     binary: bool) -> Vec<ShapeId> {
+
+    if let Some(r) = memo.get(&t) {
+        return r.clone();
+    }
+
+    let leaf = interner.leaf();
+    let ts: Vec<ShapeId> = interner.children(t).to_vec();
+
+    let mut r = HashSet::new();
+    r.insert(interner.node(&[leaf, t]));
+
+    if !ts.is_empty() && !binary {
+        let mut ts2 = ts.clone();
+        ts2.push(leaf);
+        r.insert(interner.node(&ts2));
+    }
+
+    for i in 0..ts.len() {
+        if i > 0 && ts[i] == ts[i-1] {
+            continue;
+        }
+
+        for ch2 in generate_ids_from(interner, memo, ts[i], binary) {
+            let mut ts2 = ts.clone();
+            ts2[i] = ch2;
+            r.insert(interner.node(&ts2));
+        }
+    }
+
+    let mut r: Vec<ShapeId> = r.into_iter().collect();
+    r.sort();
+
+    memo.insert(t, r.clone());
+    r
+}
+
+fn generate_ids(interner: &mut ShapeInterner, n: u32, binary: bool) -> Vec<ShapeId> {
+    let mut memo = HashMap::new();
+    let mut ts = Vec::new();
+
+    for k in 1..n+1 {
+        ts = if k == 1 {
+            vec![interner.leaf()]
+        } else {
+            let mut next = HashSet::new();
+
+            for &t in &ts {
+                next.extend(generate_ids_from(interner, &mut memo, t, binary));
+            }
+
+            let mut next: Vec<ShapeId> = next.into_iter().collect();
+            next.sort();
+            next
+        };
+    }
+
+    ts
+}
+
+pub fn all_binary_tree_ids(interner: &mut ShapeInterner, n: u32) -> Vec<ShapeId> {
+    generate_ids(interner, n, true)
+}
+
+pub fn all_tree_ids(interner: &mut ShapeInterner, n: u32) -> Vec<ShapeId> {
+    generate_ids(interner, n, false)
+}
+
+// Numbers of rooted shapes with 0 to n leaves, as the multisets of two or more (exactly two
+// for binary shapes) smaller shapes under a root.
+pub(crate) fn count_all(n: u32, binary: bool) -> Vec<u128> {
+    let n = n as usize;
+    let mut counts = vec![0u128; n+1];
+
+    for k in 1..n+1 {
+        counts[k] = if k == 1 {
+            1
+        } else {
+            let by_parts = count_multisets(&counts[..k], k);
+            if binary { by_parts[2] } else { by_parts[2..].iter().sum() }
+        };
+    }
+
+    counts
+}
+
+pub fn count_binary_trees(n: u32) -> u128 {
+    count_all(n, true)[n as usize]
+}
+
+pub fn count_trees(n: u32) -> u128 {
+    count_all(n, false)[n as usize]
+}
@@ -0,0 +1,153 @@
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::Entry;
+use std::sync::Arc;
+
+use phylo::Phylo;
+use shape::Shape;
+
+
+// Ids are handed out in creation order, so a node's children always have smaller ids than the
+// node itself. Two ids are equal iff the shapes they stand for are isomorphic.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct ShapeId(u32);
+
+impl ShapeId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+
+pub struct ShapeInterner {
+    ids: HashMap<Arc<[ShapeId]>, ShapeId>,
+    children: Vec<Arc<[ShapeId]>>,
+    kappas: Vec<u32>
+}
+
+impl Default for ShapeInterner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ShapeInterner {
+    pub fn new() -> Self {
+        let leaf_children: Arc<[ShapeId]> = Arc::new([]);
+
+        ShapeInterner {
+            ids: HashMap::new(),
+            children: vec![leaf_children],
+            kappas: vec![1]
+        }
+    }
+
+    // The number of shapes interned. The leaf is interned on creation, with id 0, so an
+    // interner is never empty.
+    pub fn len(&self) -> usize {
+        self.children.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn leaf(&self) -> ShapeId {
+        ShapeId(0)
+    }
+
+    pub fn is_leaf(&self, id: ShapeId) -> bool {
+        id.0 == 0
+    }
+
+    pub fn children(&self, id: ShapeId) -> &[ShapeId] {
+        &self.children[id.index()]
+    }
+
+    pub fn kappa(&self, id: ShapeId) -> u32 {
+        self.kappas[id.index()]
+    }
+
+    pub fn node(&mut self, children: &[ShapeId]) -> ShapeId {
+        assert!(!children.is_empty());
+
+        let mut key = children.to_vec();
+        key.sort();
+        let key: Arc<[ShapeId]> = Arc::from(key.into_boxed_slice());
+
+        let children = &mut self.children;
+        let kappas = &mut self.kappas;
+
+        match self.ids.entry(key) {
+            Entry::Occupied(e) => *e.get(),
+
+            Entry::Vacant(e) => {
+                let id = ShapeId(children.len() as u32);
+                let kappa = e.key().iter().map(|ch| kappas[ch.index()]).sum();
+
+                children.push(e.key().clone());
+                kappas.push(kappa);
+                e.insert(id);
+                id
+            }
+        }
+    }
+
+    pub fn get_node(&self, children: &[ShapeId]) -> Option<ShapeId> {
+        let mut key = children.to_vec();
+        key.sort();
+        self.ids.get(&key[..]).cloned()
+    }
+
+    pub fn intern<T>(&mut self, t: &Phylo<T>) -> ShapeId {
+        let leaf = self.leaf();
+        t.cata(|_| leaf, |_, chs| self.node(chs.as_slice()))
+    }
+
+    pub fn get<T>(&self, t: &Phylo<T>) -> Option<ShapeId> {
+        let leaf = self.leaf();
+
+        t.cata(|_| Some(leaf), |_, chs| {
+            let chs = chs.collect::<Option<Vec<_>>>()?;
+            self.get_node(&chs)
+        })
+    }
+
+    // Children are sorted like the trees produced by `generator`, and repeated subshapes are
+    // shared between the returned trees.
+    pub fn to_shape(&self, id: ShapeId) -> Shape {
+        let mut seen = HashSet::new();
+        let mut stack = vec![id];
+        seen.insert(id);
+
+        while let Some(i) = stack.pop() {
+            for &ch in self.children(i) {
+                if seen.insert(ch) {
+                    stack.push(ch);
+                }
+            }
+        }
+
+        let mut reachable: Vec<ShapeId> = seen.into_iter().collect();
+        reachable.sort();
+
+        let mut shapes: HashMap<ShapeId, Shape> = HashMap::with_capacity(reachable.len());
+
+        for i in reachable {
+            let shape = if self.is_leaf(i) {
+                Shape::leaf()
+            } else {
+                let mut chs: Vec<Shape> = self.children(i).iter().map(|ch| shapes[ch].clone()).collect();
+                chs.sort();
+                Shape::node(chs)
+            };
+
+            shapes.insert(i, shape);
+        }
+
+        shapes.remove(&id).unwrap()
+    }
+
+    pub fn isomorphic<T, U>(&mut self, t1: &Phylo<T>, t2: &Phylo<U>) -> bool {
+        self.intern(t1) == self.intern(t2)
+    }
+}
@@ -0,0 +1,68 @@
+use phylo::Phylo;
+use shape::intern::{ShapeId, ShapeInterner};
+
+
+// Caches the result of a fold for every subshape of an interner. A memo must always be used
+// with the same interner and the same folding function.
+pub struct ShapeMemo<R> {
+    values: Vec<Option<R>>
+}
+
+impl<R: Clone> Default for ShapeMemo<R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R: Clone> ShapeMemo<R> {
+    pub fn new() -> Self {
+        ShapeMemo {
+            values: Vec::new()
+        }
+    }
+
+    pub fn get(&self, id: ShapeId) -> Option<&R> {
+        self.values.get(id.index()).and_then(|v| v.as_ref())
+    }
+
+    pub fn eval<F>(&mut self, interner: &ShapeInterner, id: ShapeId, mut f: F) -> R
+        where F: FnMut(&ShapeInterner, ShapeId, &[R]) -> R {
+
+        if self.values.len() < interner.len() {
+            self.values.resize(interner.len(), None);
+        }
+
+        let mut stack = vec![(id, false)];
+        let mut rec = Vec::new();
+
+        while let Some((i, expanded)) = stack.pop() {
+            if self.values[i.index()].is_some() {
+                continue;
+            }
+
+            let children = interner.children(i);
+
+            if expanded {
+                rec.clear();
+                rec.extend(children.iter().map(|ch| self.values[ch.index()].clone().unwrap()));
+
+                let r = f(interner, i, &rec);
+                self.values[i.index()] = Some(r);
+            } else {
+                stack.push((i, true));
+                stack.extend(children.iter()
+                    .filter(|ch| self.values[ch.index()].is_none())
+                    .map(|&ch| (ch, false)));
+            }
+        }
+
+        self.values[id.index()].clone().unwrap()
+    }
+
+    pub fn fold<T, F>(&mut self, interner: &mut ShapeInterner, t: &Phylo<T>, f: F) -> R
+        where F: FnMut(&ShapeInterner, ShapeId, &[R]) -> R {
+
+        let id = interner.intern(t);
+        self.eval(interner, id, f)
+    }
+}
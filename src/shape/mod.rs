@@ -46,13 +46,15 @@ impl Shape {
 
 impl<T> Phylo<T> {
     pub fn clone_shape(&self) -> Shape {
-        match self {
-            Leaf(..)     => Shape::leaf(),
-            Node(ref ts) => Shape::node(ts.iter().map(|ch| ch.clone_shape()).collect())
-        }
+        self.cata(|_| Shape::leaf(), |_, chs| Shape::node(chs.collect()))
     }
 }
 
 pub mod balance;
+pub mod distance;
 pub mod generator;
+pub mod intern;
+pub mod memo;
 pub mod newick;
+pub mod ranked;
+pub mod unrooted;
@@ -0,0 +1,220 @@
+use rand::Rng;
+
+use phylo::Phylo;
+use phylo::ranked::{Part, RankedTree};
+use shape::Tip;
+use util::checked_factorial_over;
+
+
+// Ranked tree shapes, or Tajima trees: unlabelled trees with their internal nodes ordered in
+// time. Leaves below the same node are interchangeable, so the shape of a ranked tree only
+// depends on the parent of every event and the number of leaves below it.
+pub type RankedShape = RankedTree<Tip>;
+
+fn build(parents: &[usize], leaves: &[usize]) -> RankedShape {
+    let mut events: Vec<Vec<Part<Tip>>> = leaves.iter()
+        .map(|&l| (0..l).map(|_| Part::Leaf(Tip{})).collect())
+        .collect();
+
+    for (r, &p) in parents.iter().enumerate().skip(1) {
+        events[p].push(Part::Event(r));
+    }
+
+    RankedTree::assemble(&events)
+}
+
+// Adds the events from rank `parents.len()` on, each splitting a leaf of an earlier event.
+fn add_ranked_shapes(n: usize, parents: &mut Vec<usize>, leaves: &mut Vec<usize>, result: &mut Vec<RankedShape>) {
+    if parents.len() + 1 == n {
+        result.push(build(parents, leaves));
+        return;
+    }
+
+    for p in 0..leaves.len() {
+        if leaves[p] == 0 {
+            continue;
+        }
+
+        leaves[p] -= 1;
+        parents.push(p);
+        leaves.push(2);
+
+        add_ranked_shapes(n, parents, leaves, result);
+
+        leaves.pop();
+        parents.pop();
+        leaves[p] += 1;
+    }
+}
+
+// The binary ranked shapes with n leaves.
+pub fn all_ranked_shapes(n: usize) -> Vec<RankedShape> {
+    let mut result = Vec::new();
+
+    if n > 1 {
+        add_ranked_shapes(n, &mut vec![0], &mut vec![2], &mut result);
+    }
+
+    result
+}
+
+// The Euler zigzag number E(n-1), computed with the boustrophedon transform. A single leaf
+// has one shape with no events.
+pub fn count_ranked_shapes(n: usize) -> u128 {
+    if n == 0 {
+        return 0;
+    }
+
+    let mut row = vec![1u128];
+
+    for k in 1..n {
+        let mut next = vec![0u128; k+1];
+        for i in 1..k+1 {
+            next[i] = next[i-1].checked_add(row[k-i]).expect("too many rankings to count");
+        }
+        row = next;
+    }
+
+    *row.last().unwrap()
+}
+
+
+impl<T: Clone> RankedTree<T> {
+    pub fn shape(&self) -> RankedShape {
+        let events: Vec<Vec<Part<Tip>>> = self.events().into_iter()
+            .map(|(chs, leaves)| chs.into_iter().map(Part::Event)
+                .chain((0..leaves).map(|_| Part::Leaf(Tip{})))
+                .collect())
+            .collect();
+
+        RankedTree::assemble(&events)
+    }
+}
+
+impl RankedShape {
+    // The probability of the shape under the Kingman coalescent or the Yule model,
+    // 2^(n-c-1)/(n-1)! for a binary tree with n leaves and c cherries.
+    pub fn coalescent_probability(&self) -> f64 {
+        assert!(self.is_binary(), "not a binary tree");
+
+        let n = self.num_leaves() as i32;
+        let cherries = self.events().iter().filter(|&&(_, leaves)| leaves == 2).count() as i32;
+
+        2f64.powi(n - cherries - 1) / (1..n).map(f64::from).product::<f64>()
+    }
+
+    // A random binary ranked shape under the Yule model, splitting a uniformly chosen leaf at
+    // every event. It has the same distribution as the shape of a coalescent tree.
+    pub fn random_yule<R: Rng>(n: usize, rng: &mut R) -> Self {
+        assert!(n > 1, "no events with fewer than two leaves");
+
+        let mut parents = vec![0];
+        let mut leaves = vec![2];
+
+        for r in 1..n-1 {
+            let mut k = rng.random_range(0..r + 1);
+            let p = leaves.iter().position(|&l| if k < l { true } else { k -= l; false }).unwrap();
+
+            leaves[p] -= 1;
+            parents.push(p);
+            leaves.push(2);
+        }
+
+        build(&parents, &leaves)
+    }
+}
+
+
+impl<T: Eq> Phylo<T> {
+    // The number of labelled histories, that is ranked labelled trees, with the shape of this
+    // tree: n!/aut labellings times the rankings of each.
+    pub fn count_labelled_histories(&self) -> u128 {
+        let n = self.get_leaves().len();
+        let classes: Vec<usize> = self.symmetry_classes().into_iter().map(|(k, _)| k).collect();
+
+        checked_factorial_over(n, &classes)
+            .and_then(|labellings| labellings.checked_mul(self.count_rankings()))
+            .expect("too many rankings to count")
+    }
+
+    // The number of ranked shapes with the shape of this tree. Automorphisms act on rankings,
+    // fixing only those that permute leaves with the same parent, so the rankings are divided
+    // by the permutations of the other classes of isomorphic subtrees.
+    pub fn count_ranked_shapes(&self) -> u128 {
+        self.symmetry_classes().into_iter()
+            .filter(|&(_, leaves)| !leaves)
+            .flat_map(|(k, _)| 2 .. k as u128 + 1)
+            .fold(self.count_rankings(), |rankings, i| rankings / i)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use phylo::Phylo;
+    use super::{all_ranked_shapes, count_ranked_shapes};
+
+    fn caterpillar(n: usize) -> Phylo<()> {
+        (1..n).fold(Phylo::leaf_with(()), |t, _| Phylo::node(vec![t, Phylo::leaf_with(())]))
+    }
+
+    #[test]
+    fn ranked_shapes_are_counted_by_euler_zigzag_numbers() {
+        // OEIS A000111.
+        let zigzag: [u128; 12] = [1, 1, 1, 2, 5, 16, 61, 272, 1385, 7936, 50521, 353792];
+
+        assert_eq!(count_ranked_shapes(0), 0);
+
+        for n in 1..13 {
+            assert_eq!(count_ranked_shapes(n), zigzag[n - 1]);
+        }
+
+        for n in 2..9 {
+            assert_eq!(all_ranked_shapes(n).len() as u128, count_ranked_shapes(n));
+        }
+    }
+
+    #[test]
+    fn large_caterpillars_have_one_ranking() {
+        for &n in &[130, 140] {
+            let t = caterpillar(n);
+
+            assert_eq!(t.count_rankings(), 1);
+            assert_eq!(t.count_ranked_shapes(), 1);
+        }
+    }
+
+    #[test]
+    fn labelled_histories_do_not_need_the_factorial_of_the_leaves() {
+        // 36! does not fit, but a star of twelve leaves next to a caterpillar of 24 has
+        // 36!/(12! 2) labellings and 24 rankings.
+        let star = Phylo::node(vec![Phylo::leaf_with(()); 12]);
+        let t = Phylo::node(vec![star, caterpillar(24)]);
+
+        assert_eq!(t.count_labelled_histories(), (13..37).product::<u128>() / 2 * 24);
+        assert_eq!(caterpillar(34).count_labelled_histories(), (1..35).product::<u128>() / 2);
+    }
+
+    #[test]
+    fn large_symmetric_classes_are_not_multiplied_out() {
+        for &n in &[13, 40] {
+            let star = Phylo::node(vec![Phylo::leaf_with(()); n]);
+
+            assert_eq!(star.count_labelled_histories(), 1);
+            assert_eq!(star.count_ranked_shapes(), 1);
+        }
+
+        // Three cherries below the root: 6!/48 labellings, 3! rankings and one ranked shape.
+        let cherry = Phylo::node(vec![Phylo::leaf_with(()); 2]);
+        let t = Phylo::node(vec![cherry; 3]);
+
+        assert_eq!(t.count_labelled_histories(), 15 * 6);
+        assert_eq!(t.count_ranked_shapes(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "too many rankings to count")]
+    fn labelled_histories_that_do_not_fit_panic() {
+        caterpillar(130).count_labelled_histories();
+    }
+}
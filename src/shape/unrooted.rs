@@ -0,0 +1,407 @@
+use std::convert::TryFrom;
+
+use phylo::Phylo;
+use phylo::arena::NodeId;
+use phylo::unrooted::UnrootedTree;
+use shape::Shape;
+use shape::generator::{all_binary_tree_ids, all_tree_ids, count_all};
+use shape::intern::{ShapeId, ShapeInterner};
+use util::{binom2, count_multisets};
+
+
+// Unrooted shapes are represented by rooted shapes hung from their leaf centroid: the edge
+// splitting the leaves in two halves if there is one, and otherwise the only node where every
+// branch has fewer than half of the leaves. Two unrooted trees have the same shape iff these
+// rooted shapes are isomorphic.
+
+// The number of leaves on the far side of every edge, in the order of the neighbours of each
+// node.
+fn branch_sizes<T: Clone>(t: &UnrootedTree<T>) -> Vec<Vec<u32>> {
+    let mut parent = vec![None; t.len()];
+    let mut order = Vec::with_capacity(t.len());
+    let mut stack = vec![NodeId::from_index(0)];
+
+    while let Some(u) = stack.pop() {
+        order.push(u);
+
+        for v in t.neighbours(u) {
+            if Some(v) != parent[u.index()] {
+                parent[v.index()] = Some(u);
+                stack.push(v);
+            }
+        }
+    }
+
+    let mut below = vec![0u32; t.len()];
+
+    for &u in order.iter().rev() {
+        if t.is_leaf(u) {
+            below[u.index()] += 1;
+        }
+
+        if let Some(p) = parent[u.index()] {
+            below[p.index()] += below[u.index()];
+        }
+    }
+
+    let n = below[0];
+
+    t.node_ids()
+        .map(|u| t.neighbours(u)
+            .map(|v| if Some(v) == parent[u.index()] { n - below[u.index()] } else { below[v.index()] })
+            .collect())
+        .collect()
+}
+
+// The shape of an unrooted tree without nodes of degree two.
+pub fn unrooted_shape_id<T: Clone>(interner: &mut ShapeInterner, t: &UnrootedTree<T>) -> ShapeId {
+    let sizes = branch_sizes(t);
+    let n = t.leaves().count() as u32;
+
+    for u in t.node_ids() {
+        if let Some(v) = t.neighbours(u).zip(&sizes[u.index()]).find(|&(_, &s)| 2*s == n).map(|(v, _)| v) {
+            return interner.intern(&t.root_on_edge(u, v).to_phylo());
+        }
+    }
+
+    let centroid = t.node_ids().find(|u| sizes[u.index()].iter().all(|&s| 2*s < n)).unwrap();
+    interner.intern(&t.root_at_node(centroid).to_phylo())
+}
+
+// Multisets of the candidates, sorted by number of leaves, with `n` leaves in total and
+// `parts` elements if given.
+fn add_multisets(interner: &mut ShapeInterner,
+                 candidates: &[ShapeId],
+                 n: u32,
+                 parts: Option<usize>,
+                 current: &mut Vec<ShapeId>,
+                 result: &mut Vec<ShapeId>) {
+
+    if n == 0 {
+        if parts.is_none_or(|p| p == current.len()) {
+            result.push(interner.node(current));
+        }
+        return;
+    }
+
+    if parts.is_some_and(|p| p == current.len()) {
+        return;
+    }
+
+    for i in 0..candidates.len() {
+        let kappa = interner.kappa(candidates[i]);
+        if kappa > n {
+            break;
+        }
+
+        current.push(candidates[i]);
+        add_multisets(interner, &candidates[i..], n - kappa, parts, current, result);
+        current.pop();
+    }
+}
+
+fn generate_unrooted_ids(interner: &mut ShapeInterner, n: u32, binary: bool) -> Vec<ShapeId> {
+    if n <= 1 {
+        return vec![interner.leaf(); n as usize];
+    }
+
+    let rooted = |interner: &mut ShapeInterner, k| {
+        if binary { all_binary_tree_ids(interner, k) } else { all_tree_ids(interner, k) }
+    };
+
+    let mut result = Vec::new();
+
+    // A centroid node, with three or more branches of fewer than n/2 leaves each.
+    let mut candidates = Vec::new();
+    for k in 1..n.div_ceil(2) {
+        candidates.extend(rooted(interner, k));
+    }
+
+    let parts = if binary { Some(3) } else { None };
+    add_multisets(interner, &candidates, n, parts, &mut Vec::new(), &mut result);
+
+    // A centroid edge, with n/2 leaves on each side.
+    if n.is_multiple_of(2) {
+        let halves = rooted(interner, n/2);
+
+        for i in 0..halves.len() {
+            for j in i..halves.len() {
+                result.push(interner.node(&[halves[i], halves[j]]));
+            }
+        }
+    }
+
+    result.sort();
+    result
+}
+
+pub fn all_unrooted_binary_tree_ids(interner: &mut ShapeInterner, n: u32) -> Vec<ShapeId> {
+    generate_unrooted_ids(interner, n, true)
+}
+
+pub fn all_unrooted_tree_ids(interner: &mut ShapeInterner, n: u32) -> Vec<ShapeId> {
+    generate_unrooted_ids(interner, n, false)
+}
+
+pub fn all_unrooted_binary_trees(n: u32) -> Vec<Shape> {
+    let mut interner = ShapeInterner::new();
+    let ids = all_unrooted_binary_tree_ids(&mut interner, n);
+    ids.into_iter().map(|id| interner.to_shape(id)).collect()
+}
+
+pub fn all_unrooted_trees(n: u32) -> Vec<Shape> {
+    let mut interner = ShapeInterner::new();
+    let ids = all_unrooted_tree_ids(&mut interner, n);
+    ids.into_iter().map(|id| interner.to_shape(id)).collect()
+}
+
+fn count_unrooted(n: u32, binary: bool) -> u128 {
+    if n <= 1 {
+        return n as u128;
+    }
+
+    let rooted = count_all(n/2, binary);
+
+    let by_parts = count_multisets(&rooted[..n.div_ceil(2) as usize], n as usize);
+    let at_node = if binary { by_parts.get(3).cloned().unwrap_or(0) } else { by_parts.iter().sum() };
+
+    let at_edge = if n.is_multiple_of(2) {
+        let halves = rooted[(n/2) as usize];
+        halves * (halves + 1) / 2
+    } else {
+        0
+    };
+
+    at_node + at_edge
+}
+
+pub fn count_unrooted_binary_trees(n: u32) -> u128 {
+    count_unrooted(n, true)
+}
+
+pub fn count_unrooted_trees(n: u32) -> u128 {
+    count_unrooted(n, false)
+}
+
+
+// Balance indices of unrooted trees, which do not depend on where a tree is rooted.
+impl<T: Clone> UnrootedTree<T> {
+    // Nodes of degree three next to exactly two leaves.
+    pub fn count_cherries(&self) -> u32 {
+        self.node_ids()
+            .filter(|&u| self.degree(u) == 3 && self.neighbours(u).filter(|&v| self.is_leaf(v)).count() == 2)
+            .count() as u32
+    }
+
+    // The sum over all edges of the number of leaves on their smaller side.
+    pub fn sackin_index(&self) -> u32 {
+        let sizes = branch_sizes(self);
+        let n = self.leaves().count() as u32;
+
+        self.node_ids()
+            .flat_map(|u| self.neighbours(u).zip(&sizes[u.index()])
+                .filter(move |&(v, _)| u < v)
+                .map(move |(_, &s)| s.min(n - s)))
+            .sum()
+    }
+
+    // The sum over all internal nodes of the differences in number of leaves between every
+    // pair of their branches.
+    pub fn colless_index(&self) -> u32 {
+        branch_sizes(self).iter()
+            .map(|s| map_indices!(i in 0..s.len(), j in i+1..s.len() => {
+                (s[i] as i32 - s[j] as i32).unsigned_abs()
+            }).sum::<u32>())
+            .sum()
+    }
+
+    // The sum of the values of the quartets of leaves, by whether they are resolved or a star.
+    // By default it counts the star quartets. Counts are taken in u128, as the number of quartets
+    // overflows u64 at around 65k leaves.
+    pub fn quartet_index(&self, quartet_values: Option<&[u32; 2]>) -> u64 {
+        let vs = quartet_values.unwrap_or(&[0, 1]);
+
+        let (resolved, star) = branch_sizes(self).iter()
+            .map(|s| {
+                // Elementary symmetric polynomials of the branch sizes, up to degree four.
+                let mut e = [1u128, 0, 0, 0, 0];
+                for &x in s {
+                    for k in (1..5).rev() {
+                        e[k] += e[k-1] * x as u128;
+                    }
+                }
+
+                // Leaves a and b in two branches and c and d together in a third one. Every
+                // resolved quartet ab|cd is found at the nodes joining a to b and c to d.
+                let pairs: u128 = s.iter().map(|&x| binom2(x as u128)).sum();
+                let total: u128 = s.iter().map(|&x| x as u128).sum();
+                let overlap: u128 = s.iter().map(|&x| x as u128 * binom2(x as u128) * (total - x as u128)).sum();
+
+                (e[2] * pairs - overlap, e[4])
+            })
+            .fold((0, 0), |(r, s), (r1, s1)| (r + r1, s + s1));
+
+        u64::try_from(vs[0] as u128 * resolved / 2 + vs[1] as u128 * star).expect("quartet index does not fit in u64")
+    }
+}
+
+
+impl<T: Clone> Phylo<T> {
+    // The shape of the tree as an unrooted tree, hung from its leaf centroid.
+    pub fn unrooted_shape(&self) -> Shape {
+        let mut interner = ShapeInterner::new();
+        let id = unrooted_shape_id(&mut interner, &self.unroot());
+        interner.to_shape(id)
+    }
+
+    pub fn unrooted_isomorphic<U: Clone>(&self, other: &Phylo<U>) -> bool {
+        let mut interner = ShapeInterner::new();
+        unrooted_shape_id(&mut interner, &self.unroot()) == unrooted_shape_id(&mut interner, &other.unroot())
+    }
+
+    pub fn count_unrooted_cherries(&self) -> u32 {
+        self.unroot().count_cherries()
+    }
+
+    pub fn unrooted_sackin_index(&self) -> u32 {
+        self.unroot().sackin_index()
+    }
+
+    pub fn unrooted_colless_index(&self) -> u32 {
+        self.unroot().colless_index()
+    }
+
+    pub fn unrooted_quartet_index(&self, quartet_values: Option<&[u32; 2]>) -> u64 {
+        self.unroot().quartet_index(quartet_values)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    use phylo::{LabelledTrees, Phylo};
+    use shape::generator::{all_binary_tree_ids, all_tree_ids};
+    use shape::intern::ShapeInterner;
+    use super::*;
+
+    fn leaf(x: u32) -> Phylo<u32> {
+        Phylo::leaf_with(x)
+    }
+
+    // ((0, 1), (2, 3))
+    fn quartet() -> Phylo<u32> {
+        Phylo::node(vec![Phylo::node(vec![leaf(0), leaf(1)]), Phylo::node(vec![leaf(2), leaf(3)])])
+    }
+
+    #[test]
+    fn shapes_match_the_unrooted_rooted_shapes() {
+        for &binary in &[true, false] {
+            for n in 1..10 {
+                let mut interner = ShapeInterner::new();
+                let rooted = if binary { all_binary_tree_ids(&mut interner, n) } else { all_tree_ids(&mut interner, n) };
+
+                let expected: BTreeSet<ShapeId> = rooted.into_iter()
+                    .map(|id| {
+                        let t = interner.to_shape(id);
+                        unrooted_shape_id(&mut interner, &t.unroot())
+                    })
+                    .collect();
+
+                let ids = if binary {
+                    all_unrooted_binary_tree_ids(&mut interner, n)
+                } else {
+                    all_unrooted_tree_ids(&mut interner, n)
+                };
+                let count = if binary { count_unrooted_binary_trees(n) } else { count_unrooted_trees(n) };
+
+                assert_eq!(ids.iter().cloned().collect::<BTreeSet<ShapeId>>(), expected);
+                assert_eq!(ids.len(), expected.len());
+                assert_eq!(count, expected.len() as u128);
+            }
+        }
+
+        assert_eq!(count_unrooted_binary_trees(0), 0);
+        assert_eq!(count_unrooted_binary_trees(10), 11);
+    }
+
+    #[test]
+    fn isomorphism_ignores_the_root() {
+        let caterpillar = Phylo::node(vec![leaf(0), Phylo::node(vec![leaf(1), Phylo::node(vec![leaf(2), leaf(3)])])]);
+        let star = Phylo::node(vec![leaf(0), leaf(1), leaf(2), leaf(3)]);
+
+        assert!(quartet().unrooted_isomorphic(&caterpillar));
+        assert!(!quartet().unrooted_isomorphic(&star));
+        assert_eq!(caterpillar.unrooted_shape(), quartet().unrooted_shape());
+        assert_eq!(leaf(0).unrooted_shape(), Shape::leaf());
+    }
+
+    #[test]
+    fn indices_of_small_trees() {
+        let star = Phylo::node(vec![leaf(0), leaf(1), leaf(2), leaf(3)]);
+
+        assert_eq!(quartet().count_unrooted_cherries(), 2);
+        assert_eq!(quartet().unrooted_sackin_index(), 4 + 2);
+        assert_eq!(quartet().unrooted_colless_index(), 2 + 2);
+        assert_eq!(quartet().unrooted_quartet_index(None), 0);
+        assert_eq!(quartet().unrooted_quartet_index(Some(&[1, 0])), 1);
+
+        assert_eq!(star.count_unrooted_cherries(), 0);
+        assert_eq!(star.unrooted_sackin_index(), 4);
+        assert_eq!(star.unrooted_colless_index(), 0);
+        assert_eq!(star.unrooted_quartet_index(None), 1);
+
+        assert_eq!(leaf(0).unrooted_sackin_index(), 0);
+        assert_eq!(leaf(0).unrooted_quartet_index(None), 0);
+    }
+
+    // Counts the resolved and star quartets of a tree one by one.
+    fn brute_force_quartets(t: &Phylo<u32>, n: u32) -> (u64, u64) {
+        let (mut resolved, mut star) = (0, 0);
+
+        for a in 0..n {
+            for b in a+1..n {
+                for c in b+1..n {
+                    for d in c+1..n {
+                        if t.restrict(&[a, b, c, d]).deroot().children().len() == 4 {
+                            star += 1;
+                        } else {
+                            resolved += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        (resolved, star)
+    }
+
+    #[test]
+    fn quartet_indices_of_multifurcating_trees_match_brute_force() {
+        let mut rng = StdRng::seed_from_u64(48);
+        let labels: Vec<u32> = (0..9).collect();
+        let trees = LabelledTrees::all(&labels);
+
+        for _ in 0..30 {
+            let t = trees.unrank(rng.random_range(0..trees.num_trees()));
+            let (resolved, star) = brute_force_quartets(&t, 9);
+
+            assert_eq!(t.unrooted_quartet_index(Some(&[1, 0])), resolved);
+            assert_eq!(t.unrooted_quartet_index(None), star);
+            assert_eq!(t.unrooted_quartet_index(Some(&[2, 3])), 2*resolved + 3*star);
+        }
+    }
+
+    #[test]
+    fn quartet_index_of_a_large_star_does_not_truncate() {
+        let n = 70_000u64;
+        let star = Phylo::node((0..n as u32).map(Phylo::leaf_with).collect());
+
+        assert_eq!(star.unrooted_quartet_index(None), n*(n-1)/2 * (n-2)*(n-3)/12);
+        assert_eq!(star.unrooted_quartet_index(Some(&[1, 0])), 0);
+    }
+}
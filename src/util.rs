@@ -1,5 +1,4 @@
 use std::ops::*;
-use std::iter::Product;
 
 
 pub fn binom2<T>(n: T) -> T
@@ -8,12 +7,56 @@ pub fn binom2<T>(n: T) -> T
     n*(n - 1_u32.into()) / 2_u32.into()
 }
 
-pub fn factorial<T>(n: T) -> T
-    where T: Copy + Add<Output=T> + Mul<Output=T> + Product + From<u32>,
-          Range<T>: Iterator<Item=T> {
+pub fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        let r = a % b;
+        a = b;
+        b = r;
+    }
 
-    let one = 1_u32.into();
-    (one .. n+one).product::<T>()
+    a
+}
+
+pub fn checked_factorial(n: usize) -> Option<u128> {
+    (1 .. n as u128 + 1).try_fold(1u128, |f, i| f.checked_mul(i))
+}
+
+// n! divided by the factorials of `ks`, whose product must divide it, or None when it does not
+// fit. The quotient is multiplied out from its prime factorisation, so no partial product
+// exceeds it and the divisor is never formed.
+pub fn checked_factorial_over(n: usize, ks: &[usize]) -> Option<u128> {
+    // The exponent of every integer up to n, once in n! and once less for every k! above it.
+    let mut exponents = vec![1i64; n + 1];
+    for &k in ks {
+        assert!(k <= n, "not a divisor of the factorial");
+        for e in &mut exponents[2 .. k+1] {
+            *e -= 1;
+        }
+    }
+
+    let mut smallest_factor: Vec<usize> = (0 .. n+1).collect();
+    for p in (2 .. n+1).take_while(|p| p * p <= n) {
+        if smallest_factor[p] == p {
+            for m in (p * p .. n+1).step_by(p) {
+                smallest_factor[m] = smallest_factor[m].min(p);
+            }
+        }
+    }
+
+    // Composite numbers hand their exponents down to their factors, larger numbers first.
+    for m in (2 .. n+1).rev() {
+        let p = smallest_factor[m];
+        if p != m {
+            exponents[p] += exponents[m];
+            exponents[m / p] += exponents[m];
+            exponents[m] = 0;
+        }
+    }
+
+    (2 .. n+1).try_fold(1u128, |q, p| {
+        assert!(exponents[p] >= 0, "not a divisor of the factorial");
+        q.checked_mul((p as u128).checked_pow(exponents[p] as u32)?)
+    })
 }
 
 macro_rules! map_indices {
@@ -26,3 +69,167 @@ macro_rules! map_indices {
         $range.map(move |$var| $body)
     };
 }
+
+// Hungarian algorithm: a minimum cost assignment of every row to a distinct column, for a
+// matrix with no more rows than columns. Returns the cost and the column of every row.
+pub fn min_cost_assignment(cost: &[Vec<i64>]) -> (i64, Vec<usize>) {
+    let n = cost.len();
+    if n == 0 {
+        return (0, Vec::new());
+    }
+
+    let m = cost[0].len();
+    assert!(n <= m, "more rows than columns");
+
+    let inf = i64::MAX / 2;
+    let mut u = vec![0; n + 1];
+    let mut v = vec![0; m + 1];
+    let mut p = vec![0; m + 1];
+    let mut way = vec![0; m + 1];
+
+    for i in 1 ..= n {
+        p[0] = i;
+        let mut j0 = 0;
+        let mut minv = vec![inf; m + 1];
+        let mut used = vec![false; m + 1];
+
+        loop {
+            used[j0] = true;
+            let i0 = p[j0];
+            let mut delta = inf;
+            let mut j1 = 0;
+
+            for j in 1 ..= m {
+                if !used[j] {
+                    let cur = cost[i0 - 1][j - 1] - u[i0] - v[j];
+
+                    if cur < minv[j] {
+                        minv[j] = cur;
+                        way[j] = j0;
+                    }
+
+                    if minv[j] < delta {
+                        delta = minv[j];
+                        j1 = j;
+                    }
+                }
+            }
+
+            for j in 0 ..= m {
+                if used[j] {
+                    u[p[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    minv[j] -= delta;
+                }
+            }
+
+            j0 = j1;
+            if p[j0] == 0 {
+                break;
+            }
+        }
+
+        loop {
+            let j1 = way[j0];
+            p[j0] = p[j1];
+            j0 = j1;
+
+            if j0 == 0 {
+                break;
+            }
+        }
+    }
+
+    let mut assignment = vec![0; n];
+    for j in 1 ..= m {
+        if p[j] != 0 {
+            assignment[p[j] - 1] = j - 1;
+        }
+    }
+
+    let total = assignment.iter().enumerate().map(|(i, &j)| cost[i][j]).sum();
+    (total, assignment)
+}
+
+// The number of multisets of objects of total size `n`, by number of elements, when there
+// are `counts[k]` distinct objects of size k. Sizes beyond the slice are not used.
+pub fn count_multisets(counts: &[u128], n: usize) -> Vec<u128> {
+    let mut table = vec![vec![0u128; n+1]; n+1];
+    table[0][0] = 1;
+
+    for (k, &c) in counts.iter().enumerate().skip(1).filter(|&(k, &c)| k <= n && c > 0) {
+        let prev = table.clone();
+
+        for parts in 0..n {
+            for size in 0..n {
+                if prev[parts][size] == 0 {
+                    continue;
+                }
+
+                // Ways of choosing m objects of size k with repetition.
+                let mut ways = 1u128;
+                let mut m = 1;
+
+                while parts + m <= n && size + m*k <= n {
+                    ways = ways * (c + m as u128 - 1) / m as u128;
+                    table[parts + m][size + m*k] += prev[parts][size] * ways;
+                    m += 1;
+                }
+            }
+        }
+    }
+
+    table.into_iter().map(|row| row[n]).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    use super::min_cost_assignment;
+
+    // Tries every injective assignment of rows to columns.
+    fn brute_force_assignment(cost: &[Vec<i64>], row: usize, used: &mut Vec<bool>) -> i64 {
+        if row == cost.len() {
+            return 0;
+        }
+
+        let mut best = i64::MAX;
+
+        for j in 0..used.len() {
+            if !used[j] {
+                used[j] = true;
+                best = best.min(cost[row][j] + brute_force_assignment(cost, row + 1, used));
+                used[j] = false;
+            }
+        }
+
+        best
+    }
+
+    #[test]
+    fn assignments_match_brute_force() {
+        let mut rng = StdRng::seed_from_u64(38);
+
+        for _ in 0..200 {
+            let n = rng.random_range(0..6);
+            let m = rng.random_range(n.max(1)..7);
+            let cost: Vec<Vec<i64>> = (0..n)
+                .map(|_| (0..m).map(|_| rng.random_range(-10..10)).collect())
+                .collect();
+
+            let (total, assignment) = min_cost_assignment(&cost);
+
+            assert_eq!(total, brute_force_assignment(&cost, 0, &mut vec![false; m]));
+            assert_eq!(total, assignment.iter().enumerate().map(|(i, &j)| cost[i][j]).sum::<i64>());
+
+            let mut columns = assignment.clone();
+            columns.sort();
+            columns.dedup();
+            assert_eq!(columns.len(), n);
+        }
+    }
+}